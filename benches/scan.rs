@@ -0,0 +1,85 @@
+//! Compares checking the repositories of a directory one by one, on rayon's
+//! pool with `scan_directory` and as tokio blocking tasks with
+//! `scan_directory_async`.
+//!
+//! `cargo bench --features tokio`, or `cargo bench --features tokio -- <dir>`
+//! to scan an existing directory instead of 120 generated repositories. The
+//! first run over generated repositories is mostly served from the page cache;
+//! for cold-cache numbers, drop the caches between runs.
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use git2::{Repository, Signature};
+use git_global_status::{check_path_with, scan_directory, scan_directory_async, CheckOptions};
+
+const REPOSITORIES: usize = 120;
+const FILES: usize = 50;
+const RUNS: usize = 5;
+
+fn main() {
+    // `cargo bench` passes `--bench` along.
+    let directory = env::args().skip(1).find(|arg| !arg.starts_with("--"));
+    let root = match &directory {
+        Some(root) => PathBuf::from(root),
+        None => generate_repositories(),
+    };
+    let options = CheckOptions::default();
+    let runtime = tokio::runtime::Runtime::new().expect("could not start the tokio runtime");
+
+    report("sequential", || {
+        for entry in fs::read_dir(&root).unwrap().flatten() {
+            let _ = check_path_with(&entry.path(), &options);
+        }
+    });
+    report("rayon", || {
+        scan_directory(&root, &options).unwrap();
+    });
+    report("tokio", || {
+        runtime.block_on(scan_directory_async(&root, &options)).unwrap();
+    });
+
+    if directory.is_none() {
+        let _ = fs::remove_dir_all(&root);
+    }
+}
+
+/// Prints the fastest and the median of `RUNS` runs of `scan`.
+fn report(name: &str, mut scan: impl FnMut()) {
+    let mut times: Vec<Duration> = (0..RUNS)
+        .map(|_| {
+            let started = Instant::now();
+            scan();
+            started.elapsed()
+        })
+        .collect();
+    times.sort();
+    println!("{:<12} fastest {:>8.1?}  median {:>8.1?}", name, times[0], times[RUNS / 2]);
+}
+
+/// Repositories with a commit and a modified file each, in a temporary directory.
+fn generate_repositories() -> PathBuf {
+    let root = env::temp_dir().join(format!("ggs-bench-{}", std::process::id()));
+    let signature = Signature::now("ggs", "ggs@example.com").unwrap();
+    for number in 0..REPOSITORIES {
+        let path = root.join(format!("repo-{:03}", number));
+        let repo = Repository::init(&path).unwrap();
+        for file in 0..FILES {
+            fs::write(path.join(format!("file-{}.txt", file)), format!("{}\n", file)).unwrap();
+        }
+        commit_all(&repo, &signature);
+        fs::write(path.join("file-0.txt"), "changed\n").unwrap();
+    }
+
+    root
+}
+
+fn commit_all(repo: &Repository, signature: &Signature) {
+    let mut index = repo.index().unwrap();
+    index.add_all(["*"], git2::IndexAddOption::DEFAULT, None).unwrap();
+    index.write().unwrap();
+    let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+    repo.commit(Some("HEAD"), signature, signature, "Initial commit", &tree, &[]).unwrap();
+}
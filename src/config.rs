@@ -0,0 +1,720 @@
+use std::collections::HashMap;
+use std::env;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use git_global_status::{open_repository, origin_url};
+use serde::Deserialize;
+use tracing::trace;
+
+use crate::error::AppError;
+use crate::output::write_file_atomic;
+
+const CONFIG_FILE: &str = "config.toml";
+/// Older versions stored only the default directory, as plain text.
+const LEGACY_CONFIG_FILE: &str = "config.txt";
+/// Repositories registered with `ggs add`, one path per line, followed by a
+/// tab and the `origin` URL when the repository had one.
+const REGISTRY_FILE: &str = "repos.txt";
+/// Names a config file to use instead of the default one.
+pub const CONFIG_ENV: &str = "GGS_CONFIG";
+/// Names a directory to scan when none is given, ahead of the config.
+pub const DIR_ENV: &str = "GGS_DIR";
+/// Defaults for every user on the machine, overridden by their own config.
+#[cfg(not(windows))]
+const SYSTEM_CONFIG: &str = "/etc/ggs/config.toml";
+/// Upper bound for `threads` and `--jobs`.
+pub const MAX_THREADS: usize = 1024;
+/// The profile used when `--profile` isn't given, if the config has one.
+pub const DEFAULT_PROFILE: &str = "default";
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct Config {
+    pub default_directory: Option<String>,
+    /// Directories scanned when no path is given, in addition to `default_directory`.
+    pub roots: Vec<Root>,
+    /// Per-repository settings, keyed by the repository path.
+    pub repos: HashMap<String, RepoConfig>,
+    /// Expected commit identities, checked with `--identity-check`.
+    pub identity: Vec<IdentityRule>,
+    pub maintenance: Maintenance,
+    /// Globs for directories that are never scanned, like `--exclude`.
+    pub excludes: Vec<String>,
+    /// How many repositories to check at once, like `--jobs`.
+    pub threads: Option<usize>,
+    /// Repositories that are never checked, as paths or globs.
+    pub skip: Vec<String>,
+    /// Show repositories by their directory name, like `--short`.
+    pub short_paths: bool,
+    /// Report repositories without a commit in this many days, like `--stale-days`.
+    pub stale_days: Option<u64>,
+    /// Scan directories starting with a `.`, like `--hidden`.
+    pub hidden: bool,
+    /// Scan directories on network filesystems, like `--include-network`.
+    pub include_network: bool,
+    /// Stop counting changed lines for `--verbose` past this many.
+    pub max_diff_lines: Option<usize>,
+    /// Named sets of directories and excludes, selected with `--profile`.
+    pub profiles: HashMap<String, Profile>,
+}
+
+/// Directories to scan and skip in a given context, e.g. at work.
+#[derive(Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct Profile {
+    /// Scanned instead of `default_directory` and `roots`, when there are any.
+    pub roots: Vec<Root>,
+    /// Never scanned, in addition to the top-level `excludes`.
+    pub excludes: Vec<String>,
+}
+
+/// Thresholds above which `--maintenance` suggests running `git gc`. The
+/// defaults match git's own `gc.auto` and `gc.autoPackLimit`.
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct Maintenance {
+    pub loose_objects: usize,
+    pub packs: usize,
+}
+
+impl Default for Maintenance {
+    fn default() -> Maintenance {
+        Maintenance {
+            loose_objects: 6700,
+            packs: 50,
+        }
+    }
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct RepoConfig {
+    pub expected_branch: Option<String>,
+}
+
+/// A directory whose subdirectories are scanned.
+#[derive(Deserialize, Clone)]
+pub struct Root {
+    pub path: String,
+    /// Shown in place of the root's path in the report.
+    #[serde(default)]
+    pub label: Option<String>,
+}
+
+impl Root {
+    pub fn new(path: &str) -> Root {
+        Root { path: String::from(path), label: None }
+    }
+}
+
+/// The `user.email`/`user.name` repositories are expected to use. Without a
+/// `path` the rule applies everywhere; otherwise to repositories under `path`.
+#[derive(Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct IdentityRule {
+    pub path: Option<String>,
+    /// Glob pattern, e.g. `*@company.com`.
+    pub email: Option<String>,
+    pub name: Option<String>,
+}
+
+impl IdentityRule {
+    pub fn matches(&self, name: Option<&str>, email: Option<&str>) -> bool {
+        pattern_matches(self.email.as_deref(), email) && pattern_matches(self.name.as_deref(), name)
+    }
+}
+
+/// An unset pattern matches anything; an unset value matches no pattern.
+fn pattern_matches(pattern: Option<&str>, value: Option<&str>) -> bool {
+    let pattern = match pattern {
+        Some(pattern) => pattern,
+        None => return true,
+    };
+    let value = match value {
+        Some(value) => value,
+        None => return false,
+    };
+
+    match glob::Pattern::new(pattern) {
+        Ok(glob) => glob.matches(value),
+        Err(_) => pattern == value,
+    }
+}
+
+impl Config {
+    /// Every directory to scan when none is given on the command line.
+    pub fn roots(&self) -> Vec<Root> {
+        let mut roots = self.roots.clone();
+        if let Some(default_directory) = get_default_directory(self) {
+            if !roots.iter().any(|root| root.path == default_directory) {
+                roots.insert(0, Root::new(&default_directory));
+            }
+        }
+
+        roots
+    }
+
+    /// Every path in the config.
+    fn paths(&self) -> impl Iterator<Item = &String> {
+        self.default_directory.iter()
+            .chain(self.roots.iter().map(|root| &root.path))
+            .chain(self.repos.keys())
+            .chain(self.identity.iter().filter_map(|rule| rule.path.as_ref()))
+            .chain(&self.skip)
+            .chain(self.profiles.values().flat_map(|profile| profile.roots.iter().map(|root| &root.path)))
+    }
+
+    /// Switches to the profile called `name`: its roots replace the configured
+    /// ones, and its excludes apply on top of the others.
+    pub fn use_profile(&mut self, name: &str) -> Result<(), AppError> {
+        let profile = match self.profiles.get(name) {
+            Some(profile) => profile.clone(),
+            None => {
+                let mut available: Vec<String> = self.profiles.keys().cloned().collect();
+                available.sort();
+                return Err(AppError::UnknownProfile { name: String::from(name), available });
+            }
+        };
+
+        if !profile.roots.is_empty() {
+            self.default_directory = None;
+            self.roots = profile.roots;
+        }
+        self.excludes.extend(profile.excludes);
+
+        Ok(())
+    }
+
+    /// The identity rule for the repository at `repo`. The rule with the
+    /// longest matching `path` wins, so a rule for `~/work/oss` overrides one
+    /// for `~/work`, which in turn overrides a rule without a path.
+    pub fn identity_rule(&self, repo: &Path) -> Option<IdentityRule> {
+        let mut best: Option<(usize, &IdentityRule)> = None;
+        for rule in &self.identity {
+            let depth = match &rule.path {
+                None => 0,
+                Some(path) => {
+                    let path = match expand_path(path) {
+                        Ok(path) => path,
+                        Err(_) => continue,
+                    };
+                    let path = std::fs::canonicalize(&path).unwrap_or(path);
+                    if !repo.starts_with(&path) {
+                        continue;
+                    }
+                    path.components().count()
+                }
+            };
+            if best.is_none_or(|(best_depth, _)| depth >= best_depth) {
+                best = Some((depth, rule));
+            }
+        }
+
+        best.map(|(_, rule)| rule.clone())
+    }
+
+    /// The configured excludes. A pattern that isn't a valid glob only
+    /// matches itself.
+    pub fn exclude_patterns(&self) -> Vec<glob::Pattern> {
+        self.excludes.iter()
+            .map(|exclude| glob::Pattern::new(exclude)
+                .unwrap_or_else(|_| glob::Pattern::new(&glob::Pattern::escape(exclude)).expect("escaped pattern is valid")))
+            .collect()
+    }
+
+    /// The skip list with paths expanded, along with the entries as written.
+    pub fn skip_patterns(&self) -> Vec<(String, glob::Pattern)> {
+        let mut patterns = Vec::new();
+        for entry in &self.skip {
+            let path = match expand_path(entry) {
+                Ok(path) => path,
+                Err(_) => continue,
+            };
+            let path = path.to_string_lossy();
+            let pattern = glob::Pattern::new(&path)
+                .unwrap_or_else(|_| glob::Pattern::new(&glob::Pattern::escape(&path)).expect("escaped pattern is valid"));
+            patterns.push((entry.clone(), pattern));
+        }
+
+        patterns
+    }
+
+    /// Expected branch per repository, keyed by the resolved repository path.
+    pub fn expected_branches(&self) -> HashMap<PathBuf, String> {
+        let mut branches = HashMap::new();
+        for (path, repo) in &self.repos {
+            let branch = match &repo.expected_branch {
+                Some(branch) => branch,
+                None => continue,
+            };
+            let path = match expand_path(path) {
+                Ok(path) => path,
+                Err(_) => continue,
+            };
+            let path = std::fs::canonicalize(&path).unwrap_or(path);
+            branches.insert(path, branch.clone());
+        }
+
+        branches
+    }
+}
+
+/// The config file to use: the one `GGS_CONFIG` names, or the default one.
+pub fn config_path() -> Result<PathBuf, AppError> {
+    if let Some(custom) = env::var_os(CONFIG_ENV).filter(|custom| !custom.is_empty()) {
+        let resolved = custom.to_str()
+            .and_then(|custom| expand_path(custom).ok())
+            .and_then(|path| std::fs::canonicalize(path).ok())
+            .filter(|path| path.is_file());
+        match resolved {
+            Some(path) => return Ok(path),
+            None => eprintln!("Warning: {}={} is not a file. Using the default config.", CONFIG_ENV, custom.to_string_lossy()),
+        }
+    }
+
+    Ok(config_dir()?.join(CONFIG_FILE))
+}
+
+/// The system-wide config file, `/etc/ggs/config.toml` or
+/// `%ProgramData%\ggs\config.toml` on Windows.
+fn system_config_path() -> Option<PathBuf> {
+    #[cfg(windows)]
+    {
+        env::var_os("ProgramData").map(|dir| PathBuf::from(dir).join("ggs").join(CONFIG_FILE))
+    }
+    #[cfg(not(windows))]
+    {
+        Some(PathBuf::from(SYSTEM_CONFIG))
+    }
+}
+
+/// Reads the system config with the user's config on top of it: a setting
+/// the user config has replaces the system one, tables are merged setting by
+/// setting.
+pub fn load_config() -> Result<Config, AppError> {
+    let config_path = config_path()?;
+    trace!("config: {}", config_path.display());
+
+    let mut table = match system_config_path() {
+        Some(path) => read_table(&path)?.unwrap_or_default(),
+        None => toml::Table::new(),
+    };
+    match read_table(&config_path)? {
+        Some(user) => merge_tables(&mut table, user),
+        None => {
+            trace!("config: no {}, looking for a legacy one", config_path.display());
+            if let Some(directory) = load_legacy_config(&config_dir()?)? {
+                table.insert(String::from("default_directory"), toml::Value::String(directory));
+            }
+        }
+    }
+
+    let config: Config = match toml::Value::Table(table).try_into() {
+        Ok(config) => config,
+        Err(e) => return Err(invalid_config(&config_path, e)),
+    };
+    if let Some(threads) = config.threads.filter(|&threads| threads > MAX_THREADS) {
+        return Err(invalid_config(&config_path, format!("threads must be at most {}, got {}", MAX_THREADS, threads)));
+    }
+    // Catch unset variables and unknown users once, rather than each time a
+    // path is used. Returned as they are, so the caller can tell them apart.
+    for path in config.paths() {
+        expand_path(path)?;
+    }
+
+    Ok(config)
+}
+
+/// The default directory stored by older versions, if there is one.
+fn load_legacy_config(dir: &Path) -> Result<Option<String>, AppError> {
+    let legacy_path = dir.join(LEGACY_CONFIG_FILE);
+    match std::fs::read_to_string(&legacy_path) {
+        Ok(contents) => {
+            trace!("config: read legacy {}", legacy_path.display());
+            Ok(Some(contents))
+        }
+        Err(error) if error.kind() == ErrorKind::NotFound => Ok(None),
+        Err(error) => Err(AppError::io(legacy_path, error)),
+    }
+}
+
+/// The config file at `path`, `None` if there is none.
+fn read_table(path: &Path) -> Result<Option<toml::Table>, AppError> {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(error) if error.kind() == ErrorKind::NotFound => {
+            trace!("config: {} not found", path.display());
+            return Ok(None);
+        }
+        Err(error) => return Err(AppError::io(path, error)),
+    };
+    trace!("config: read {}", path.display());
+
+    match contents.parse() {
+        Ok(table) => Ok(Some(table)),
+        Err(e) => Err(invalid_config(path, e)),
+    }
+}
+
+/// Sets everything in `overrides` on `base`. Tables are merged key by key,
+/// anything else, lists included, is replaced.
+fn merge_tables(base: &mut toml::Table, overrides: toml::Table) {
+    for (key, value) in overrides {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(value)) => merge_tables(base, value),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+fn invalid_config(path: &Path, error: impl std::fmt::Display) -> AppError {
+    AppError::InvalidConfig { path: path.to_path_buf(), message: error.to_string() }
+}
+
+pub fn set_default_directory(path: &str) -> Result<(), AppError> {
+ 
+    let mut path = expand_path(path)?;
+    if path.is_relative() {
+        let current_dir = match env::current_dir() {
+            Ok(dir) => dir,
+            Err(e) => return Err(AppError::io(".", e)),
+        };
+        path = current_dir.join(path);
+    }
+    // Saving a path that can't be scanned would only make every later run fail.
+    match std::fs::metadata(&path) {
+        Ok(metadata) if metadata.is_dir() => {}
+        Ok(_) => return Err(AppError::NotADirectory(path)),
+        Err(error) => return Err(AppError::directory(path, error)),
+    }
+    let path = match path.to_str() {
+        Some(str) => String::from(str),
+        None => return Err(AppError::NonUnicodePath(path)),
+    };
+
+    let config_path = config_path()?;
+
+    // Keep everything else the user put in the file.
+    let mut table = match std::fs::read_to_string(&config_path) {
+        Ok(contents) => match contents.parse::<toml::Table>() {
+            Ok(table) => table,
+            Err(e) => return Err(invalid_config(&config_path, e)),
+        },
+        Err(error) if error.kind() == ErrorKind::NotFound => toml::Table::new(),
+        Err(error) => return Err(AppError::io(config_path, error)),
+    };
+    table.insert(String::from("default_directory"), toml::Value::String(path));
+
+    if let Some(dir) = config_path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| AppError::io(dir, e))?;
+    } 
+
+    // An interrupted write must not leave the config empty or cut in half.
+    trace!("config: saving default_directory to {}", config_path.display());
+    write_file_atomic(&config_path, table.to_string().as_bytes()).map_err(|e| AppError::io(&config_path, e))
+}
+
+/// A repository registered with `ggs add`.
+#[derive(Clone)]
+pub struct Registered {
+    pub path: String,
+    /// Where `origin` pointed to when it was registered, to find the
+    /// repository again after it was moved.
+    pub origin: Option<String>,
+}
+
+/// The repositories registered with `ggs add`, `None` if none ever were.
+pub fn load_registry() -> Result<Option<Vec<Registered>>, AppError> {
+    let path = config_dir()?.join(REGISTRY_FILE);
+    let contents = match std::fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(error) if error.kind() == ErrorKind::NotFound => {
+            trace!("registry: {} not found", path.display());
+            return Ok(None);
+        }
+        Err(error) => return Err(AppError::io(path, error)),
+    };
+    trace!("registry: read {}", path.display());
+
+    let repositories = contents.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| match line.rsplit_once('\t') {
+            Some((path, origin)) => Registered { path: String::from(path), origin: Some(String::from(origin)) },
+            None => Registered { path: String::from(line), origin: None },
+        })
+        .collect();
+    Ok(Some(repositories))
+}
+
+/// Adds the directory at `path` to the registry. Returns its canonical path,
+/// and whether it wasn't registered already.
+pub fn register(path: &Path) -> Result<(String, bool), AppError> {
+    let path = std::fs::canonicalize(path).map_err(|e| AppError::io(path, e))?;
+    if !path.is_dir() {
+        return Err(AppError::NotADirectory(path));
+    }
+    let path = match path.to_str() {
+        Some(str) => String::from(str),
+        None => return Err(AppError::NonUnicodePath(path)),
+    };
+
+    let mut repositories = load_registry()?.unwrap_or_default();
+    if repositories.iter().any(|repository| repository.path == path) {
+        return Ok((path, false));
+    }
+    let origin = open_repository(Path::new(&path)).ok().and_then(|repo| origin_url(&repo));
+    repositories.push(Registered { path: path.clone(), origin });
+    save_registry(&repositories)?;
+
+    Ok((path, true))
+}
+
+/// Removes `path` from the registry. Returns the path as it was looked up,
+/// and whether it was registered at all.
+pub fn unregister(path: &str) -> Result<(String, bool), AppError> {
+    // The repository may well be gone already.
+    let path = expand_path(path)?;
+    let path = std::fs::canonicalize(&path).unwrap_or(path);
+    let path = path.to_string_lossy().into_owned();
+
+    let mut repositories = load_registry()?.unwrap_or_default();
+    let count = repositories.len();
+    repositories.retain(|repository| repository.path != path);
+    if repositories.len() == count {
+        return Ok((path, false));
+    }
+    save_registry(&repositories)?;
+
+    Ok((path, true))
+}
+
+/// Replaces the registered repositories with `repositories`.
+pub fn save_registry(repositories: &[Registered]) -> Result<(), AppError> {
+    let dir = config_dir()?;
+    std::fs::create_dir_all(&dir).map_err(|e| AppError::io(&dir, e))?;
+
+    let path = dir.join(REGISTRY_FILE);
+    let contents: String = repositories.iter()
+        .map(|repository| match &repository.origin {
+            Some(origin) => format!("{}\t{}\n", repository.path, origin),
+            None => format!("{}\n", repository.path),
+        })
+        .collect();
+    trace!("registry: saving {} repositories to {}", repositories.len(), path.display());
+    write_file_atomic(&path, contents.as_bytes()).map_err(|e| AppError::io(&path, e))
+}
+
+pub fn get_default_directory(config: &Config) -> Option<String> {
+    // The file may have been hand-edited and left with a trailing newline.
+    config.default_directory.as_ref().map(|dir| String::from(dir.trim()))
+}
+
+/// Home directory of the current user. `HOME` is not set on Windows, so fall
+/// back to `USERPROFILE` and then `APPDATA` before giving up.
+pub fn home_dir() -> Result<PathBuf, AppError> {
+    for var in ["HOME", "USERPROFILE", "APPDATA"] {
+        if let Some(val) = env::var_os(var) {
+            if !val.is_empty() {
+                return Ok(PathBuf::from(val));
+            }
+        }
+    }
+
+    Err(AppError::NoHomeDirectory)
+}
+
+/// Expands `$VAR`/`${VAR}` references and a leading `~` in a path given by
+/// the user, on the command line or in the config file.
+pub fn expand_path(path: &str) -> Result<PathBuf, AppError> {
+    expand_tilde(&expand_vars(path)?)
+}
+
+/// Replaces `$VAR` and `${VAR}` with the variable's value. A `$` that isn't
+/// followed by a name is kept as is.
+fn expand_vars(path: &str) -> Result<String, AppError> {
+    let mut expanded = String::new();
+    let mut rest = path;
+
+    while let Some(start) = rest.find('$') {
+        expanded.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+
+        let (name, remainder) = match after.strip_prefix('{') {
+            Some(braced) => match braced.find('}') {
+                Some(end) => (&braced[..end], &braced[end + 1..]),
+                None => ("", after),
+            },
+            None => {
+                let end = after.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(after.len());
+                (&after[..end], &after[end..])
+            }
+        };
+
+        if name.is_empty() {
+            expanded.push_str(&rest[start..rest.len() - remainder.len()]);
+        } else {
+            match env::var(name) {
+                Ok(value) => expanded.push_str(&value),
+                Err(_) => return Err(AppError::UnsetVariable { path: String::from(path), name: String::from(name) }),
+            }
+        }
+        rest = remainder;
+    }
+    expanded.push_str(rest);
+
+    Ok(expanded)
+}
+
+/// Replaces a leading `~` with the home directory, and `~user` with the home
+/// directory of `user`.
+fn expand_tilde(path: &str) -> Result<PathBuf, AppError> {
+    let after = match path.strip_prefix('~') {
+        Some(after) => after,
+        None => return Ok(PathBuf::from(path)),
+    };
+    let end = after.find(['/', '\\']).unwrap_or(after.len());
+    let (user, rest) = (&after[..end], after[end..].trim_start_matches(['/', '\\']));
+
+    let home = if user.is_empty() {
+        home_dir()?
+    } else {
+        match user_home_dir(user) {
+            Some(home) => home,
+            None => return Err(AppError::UnknownUser { path: String::from(path), name: String::from(user) }),
+        }
+    };
+
+    if rest.is_empty() {
+        Ok(home)
+    } else {
+        Ok(home.join(rest))
+    }
+}
+
+/// The home directory of `user`, as listed in `/etc/passwd`.
+#[cfg(not(windows))]
+fn user_home_dir(user: &str) -> Option<PathBuf> {
+    let passwd = std::fs::read_to_string("/etc/passwd").ok()?;
+    passwd.lines()
+        .map(|line| line.split(':').collect::<Vec<&str>>())
+        .find(|fields| fields.len() >= 6 && fields[0] == user)
+        .map(|fields| PathBuf::from(fields[5]))
+}
+
+/// The home directory of `user`, assumed to sit next to the current user's.
+#[cfg(windows)]
+fn user_home_dir(user: &str) -> Option<PathBuf> {
+    let home = home_dir().ok()?;
+    let other = home.parent()?.join(user);
+    if other.is_dir() {
+        Some(other)
+    } else {
+        None
+    }
+}
+
+#[cfg(not(windows))]
+fn config_dir() -> Result<PathBuf, AppError> {
+    Ok(home_dir()?.join(".config").join("ggs"))
+}
+
+#[cfg(windows)]
+fn config_dir() -> Result<PathBuf, AppError> {
+    match env::var_os("APPDATA") {
+        Some(appdata) if !appdata.is_empty() => Ok(PathBuf::from(appdata).join("ggs")),
+        _ => Ok(home_dir()?.join("ggs")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tempfile::TempDir;
+
+    fn rule(path: Option<&Path>, email: &str) -> IdentityRule {
+        IdentityRule {
+            path: path.map(|path| path.to_string_lossy().into_owned()),
+            email: Some(String::from(email)),
+            name: None,
+        }
+    }
+
+    /// The email pattern of the rule `identity_rule` picks for `repo`.
+    fn chosen(rules: Vec<IdentityRule>, repo: &Path) -> Option<String> {
+        let config = Config { identity: rules, ..Config::default() };
+        config.identity_rule(repo).and_then(|rule| rule.email)
+    }
+
+    #[test]
+    fn longest_matching_path_wins() {
+        let dir = TempDir::new().unwrap();
+        let work = std::fs::canonicalize(dir.path()).unwrap();
+        let oss = work.join("oss");
+        std::fs::create_dir(&oss).unwrap();
+        let rules = || vec![
+            rule(Some(&oss), "*@oss.org"),
+            rule(None, "*@personal.com"),
+            rule(Some(&work), "*@company.com"),
+        ];
+
+        assert_eq!(chosen(rules(), &oss.join("app")).as_deref(), Some("*@oss.org"));
+        assert_eq!(chosen(rules(), &work.join("app")).as_deref(), Some("*@company.com"));
+        assert_eq!(chosen(rules(), Path::new("/elsewhere/app")).as_deref(), Some("*@personal.com"));
+    }
+
+    #[test]
+    fn later_rule_wins_between_equally_long_paths() {
+        let dir = TempDir::new().unwrap();
+        let work = std::fs::canonicalize(dir.path()).unwrap();
+
+        let rules = vec![rule(Some(&work), "*@first.com"), rule(Some(&work), "*@second.com")];
+        assert_eq!(chosen(rules, &work.join("app")).as_deref(), Some("*@second.com"));
+        let rules = vec![rule(None, "*@first.com"), rule(None, "*@second.com")];
+        assert_eq!(chosen(rules, &work.join("app")).as_deref(), Some("*@second.com"));
+    }
+
+    #[test]
+    fn no_rule_for_paths_outside_every_rule() {
+        let dir = TempDir::new().unwrap();
+        let rules = vec![rule(Some(dir.path()), "*@company.com")];
+        assert_eq!(chosen(rules, Path::new("/elsewhere/app")), None);
+    }
+
+    #[test]
+    fn unset_pattern_matches_anything() {
+        assert!(pattern_matches(None, Some("me@company.com")));
+        assert!(pattern_matches(None, None));
+    }
+
+    #[test]
+    fn unset_value_matches_no_pattern() {
+        assert!(!pattern_matches(Some("*"), None));
+        assert!(!pattern_matches(Some("*@company.com"), None));
+    }
+
+    #[test]
+    fn patterns_are_globs() {
+        assert!(pattern_matches(Some("*@company.com"), Some("me@company.com")));
+        assert!(!pattern_matches(Some("*@company.com"), Some("me@personal.com")));
+        // An invalid glob only matches itself.
+        assert!(pattern_matches(Some("[me"), Some("[me")));
+        assert!(!pattern_matches(Some("[me"), Some("me")));
+    }
+
+    #[test]
+    fn rule_needs_both_name_and_email_to_match() {
+        let rule = IdentityRule {
+            path: None,
+            email: Some(String::from("*@company.com")),
+            name: Some(String::from("Jane *")),
+        };
+        assert!(rule.matches(Some("Jane Doe"), Some("jane@company.com")));
+        assert!(!rule.matches(Some("John Doe"), Some("jane@company.com")));
+        assert!(!rule.matches(None, Some("jane@company.com")));
+    }
+}
@@ -0,0 +1,101 @@
+use std::fmt;
+use std::io::{Error as IOError, ErrorKind};
+use std::path::PathBuf;
+
+use git2::ErrorCode;
+use git_global_status::GgsError;
+
+/// An error together with the file or repository it happened for.
+#[derive(Debug)]
+pub enum AppError {
+    Io { path: PathBuf, source: IOError },
+    Git { path: PathBuf, source: git2::Error },
+    InvalidConfig { path: PathBuf, message: String },
+    NonUnicodePath(PathBuf),
+    NotADirectory(PathBuf),
+    NotARepository(PathBuf),
+    /// A repository without a working tree, skipped without `--include-bare`.
+    BareRepository(PathBuf),
+    NoHomeDirectory,
+    /// A path referenced an environment variable that isn't set.
+    UnsetVariable { path: String, name: String },
+    /// A path started with `~user` for a user that doesn't exist.
+    UnknownUser { path: String, name: String },
+    InvalidPattern { pattern: String, message: String },
+    /// A glob pattern given as a root matched no directory.
+    NoMatches(String),
+    /// `--profile` named a profile the config doesn't define.
+    UnknownProfile { name: String, available: Vec<String> },
+}
+
+impl AppError {
+    pub fn io(path: impl Into<PathBuf>, source: IOError) -> AppError {
+        AppError::Io { path: path.into(), source }
+    }
+
+    /// An error from reading `path` as a directory, where a missing path is
+    /// as much not a directory as a file is.
+    pub fn directory(path: impl Into<PathBuf>, source: IOError) -> AppError {
+        match source.kind() {
+            ErrorKind::NotFound => AppError::NotADirectory(path.into()),
+            _ => AppError::io(path, source),
+        }
+    }
+
+    pub fn git(path: impl Into<PathBuf>, source: git2::Error) -> AppError {
+        AppError::Git { path: path.into(), source }
+    }
+
+    /// An error from opening or checking the repository at `path`.
+    pub fn check(path: impl Into<PathBuf>, error: GgsError) -> AppError {
+        match error {
+            GgsError::Git(source) => AppError::git(path, source),
+            GgsError::Io(source) => AppError::io(path, source),
+            GgsError::NotAGitRepo(path) => AppError::NotARepository(path),
+        }
+    }
+
+    /// The underlying error, for errors coming from git2.
+    pub fn git_error(&self) -> Option<&git2::Error> {
+        match self {
+            AppError::Git { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+
+    /// The libgit2 error code, for errors coming from git2.
+    pub fn git_code(&self) -> Option<ErrorCode> {
+        self.git_error().map(git2::Error::code)
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            AppError::Git { path, source } => write!(f, "{}: {}", path.display(), source.message()),
+            AppError::InvalidConfig { path, message } => write!(f, "{} is not valid: {}", path.display(), message),
+            AppError::NonUnicodePath(path) => write!(f, "{} is not valid UTF-8", path.display()),
+            AppError::NotADirectory(path) => write!(f, "{} is not a directory", path.display()),
+            AppError::NotARepository(path) => write!(f, "{} is not a git repository", path.display()),
+            AppError::BareRepository(path) => write!(f, "{} is a bare repository", path.display()),
+            AppError::NoHomeDirectory => write!(f, "could not determine home directory (HOME, USERPROFILE and APPDATA are unset)"),
+            AppError::UnsetVariable { path, name } => write!(f, "{}: environment variable {} is not set", path, name),
+            AppError::UnknownUser { path, name } => write!(f, "{}: no home directory for user {}", path, name),
+            AppError::InvalidPattern { pattern, message } => write!(f, "{} is not a valid pattern: {}", pattern, message),
+            AppError::NoMatches(pattern) => write!(f, "{} doesn't match any directory", pattern),
+            AppError::UnknownProfile { name, available } if available.is_empty() => write!(f, "no profile named {}, the config doesn't define any", name),
+            AppError::UnknownProfile { name, available } => write!(f, "no profile named {}, expected one of {}", name, available.join(", ")),
+        }
+    }
+}
+
+impl std::error::Error for AppError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AppError::Io { source, .. } => Some(source),
+            AppError::Git { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
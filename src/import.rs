@@ -0,0 +1,74 @@
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::config::expand_path;
+use crate::error::AppError;
+
+/// Another tool's list of repositories, for `ggs import`.
+#[derive(Clone, Copy)]
+pub enum ImportFormat {
+    /// myrepos' `.mrconfig`: a `[path]` section per repository, relative to
+    /// the directory the file is in.
+    Mr,
+    /// gita's `repos.csv`: a repository per line, its path in the first column.
+    Gita,
+}
+
+impl FromStr for ImportFormat {
+    type Err = ();
+
+    fn from_str(name: &str) -> Result<ImportFormat, ()> {
+        match name {
+            "mr" | "myrepos" => Ok(ImportFormat::Mr),
+            "gita" => Ok(ImportFormat::Gita),
+            _ => Err(()),
+        }
+    }
+}
+
+/// The repositories read from an import file.
+pub struct Imported {
+    pub paths: Vec<PathBuf>,
+    /// One for every entry that couldn't be read, the others are still imported.
+    pub warnings: Vec<String>,
+}
+
+/// Reads the repositories listed in `path`, a file in `format`.
+pub fn read_import(format: ImportFormat, path: &Path) -> Result<Imported, AppError> {
+    let contents = std::fs::read_to_string(path).map_err(|e| AppError::io(path, e))?;
+    let base = path.parent().unwrap_or(Path::new("."));
+
+    let mut imported = Imported { paths: Vec::new(), warnings: Vec::new() };
+    for (number, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        let entry = match format {
+            // Everything but the section headers configures how mr runs
+            // commands, which ggs has no use for.
+            ImportFormat::Mr if !line.starts_with('[') => continue,
+            ImportFormat::Mr => match line.strip_prefix('[').and_then(|line| line.strip_suffix(']')).map(str::trim) {
+                Some("DEFAULT") => continue,
+                Some(section) if !section.is_empty() => section,
+                _ => {
+                    imported.warnings.push(format!("{}:{}: invalid section '{}'", path.display(), number + 1, line));
+                    continue;
+                }
+            },
+            ImportFormat::Gita if line.is_empty() => continue,
+            ImportFormat::Gita => match line.split(',').next().map(str::trim) {
+                Some(column) if !column.is_empty() => column,
+                _ => {
+                    imported.warnings.push(format!("{}:{}: no repository path in '{}'", path.display(), number + 1, line));
+                    continue;
+                }
+            },
+        };
+
+        match expand_path(entry) {
+            // Joining an absolute path replaces the base.
+            Ok(expanded) => imported.paths.push(base.join(expanded)),
+            Err(error) => imported.warnings.push(format!("{}:{}: {}", path.display(), number + 1, error)),
+        }
+    }
+
+    Ok(imported)
+}
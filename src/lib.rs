@@ -0,0 +1,833 @@
+//! Checks the state of git repositories: changes in the working tree and
+//! index, operations left in progress, and commits not pushed upstream.
+//!
+//! `ggs` itself is built on top of this; other tools can use [`check_path`] to
+//! check a single repository without spawning it.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use git2::{BranchType, Error, ErrorCode, Repository, RepositoryState, StatusOptions};
+use rayon::prelude::*;
+use serde::Serialize;
+use tracing::trace;
+
+const STAGED_FLAGS: git2::Status = git2::Status::INDEX_NEW
+    .union(git2::Status::INDEX_MODIFIED)
+    .union(git2::Status::INDEX_DELETED)
+    .union(git2::Status::INDEX_RENAMED)
+    .union(git2::Status::INDEX_TYPECHANGE);
+const MODIFIED_FLAGS: git2::Status = git2::Status::WT_MODIFIED
+    .union(git2::Status::WT_DELETED)
+    .union(git2::Status::WT_RENAMED)
+    .union(git2::Status::WT_TYPECHANGE);
+
+const DEFAULT_RETRIES: u32 = 2;
+const DEFAULT_RETRY_DELAY_MS: u64 = 100;
+const DEFAULT_MAX_DIFF_LINES: usize = 10_000;
+pub const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+/// Something worth reporting about a repository.
+#[derive(Clone, Debug, PartialEq)]
+pub enum GitStatus {
+    /// A merge, rebase or similar was started and not finished; holds its name.
+    InProgress(&'static str),
+    Modified,
+    Staged,
+    UnpushedCommits,
+    /// The upstream branch was deleted on the remote, as far as the last fetch knows.
+    UpstreamGone,
+    NoUpstream,
+    /// No commit in longer than `stale_days`, or no commit at all. Kept
+    /// along with the most important status when not every status is reported.
+    Stale,
+    Empty,
+    /// Without a working tree, so there is nothing else to check.
+    BareRepo,
+}
+
+impl GitStatus {
+    /// Whether the status stands for work that isn't committed or pushed
+    /// yet. The others are worth knowing about, but leave the repository clean
+    /// for `--count`, `--fail-fast`, `--print0` and notifications.
+    pub fn is_dirty(&self) -> bool {
+        matches!(self, GitStatus::InProgress(_) | GitStatus::Modified | GitStatus::Staged | GitStatus::UnpushedCommits)
+    }
+
+    /// How the status is named in structured output.
+    pub fn name(&self) -> &'static str {
+        match self {
+            GitStatus::InProgress(_) => "in_progress",
+            GitStatus::Modified => "modified",
+            GitStatus::Staged => "staged",
+            GitStatus::UnpushedCommits => "unpushed_commits",
+            GitStatus::UpstreamGone => "upstream_gone",
+            GitStatus::NoUpstream => "no_upstream",
+            GitStatus::Stale => "stale",
+            GitStatus::Empty => "empty",
+            GitStatus::BareRepo => "bare",
+        }
+    }
+}
+
+/// How the checked out branch compares to its upstream.
+enum PushState {
+    /// HEAD is detached or unborn, or the upstream couldn't be resolved.
+    Unknown,
+    NoUpstream,
+    /// An upstream is configured, but its remote-tracking branch no longer exists.
+    UpstreamGone,
+    Tracking { upstream: String, ahead: usize, behind: usize },
+}
+
+/// How [`check_status`] checks a repository. The defaults are what `ggs`
+/// uses without any flags.
+#[derive(Clone, Debug)]
+pub struct CheckOptions {
+    /// How many times to retry a status check that failed on a locked index.
+    pub retries: u32,
+    pub retry_delay: Duration,
+    /// Don't report branches without an upstream.
+    pub ignore_no_upstream: bool,
+    /// Don't compare branches with their upstream at all.
+    pub no_upstream_check: bool,
+    /// Check every local branch against its upstream, not just the current one.
+    pub all_branches: bool,
+    /// Report every status that applies instead of only the most important one.
+    pub all_statuses: bool,
+    /// Only report modified/staged changes older than this many days.
+    pub dirty_for: Option<u64>,
+    /// Report repositories without a commit in this many days as stale.
+    pub stale_days: Option<u64>,
+    /// Count the lines changed in the working tree and index.
+    pub diff_stat: bool,
+    /// Give up counting changed lines past this many.
+    pub max_diff_lines: usize,
+}
+
+impl Default for CheckOptions {
+    fn default() -> CheckOptions {
+        CheckOptions {
+            retries: DEFAULT_RETRIES,
+            retry_delay: Duration::from_millis(DEFAULT_RETRY_DELAY_MS),
+            ignore_no_upstream: false,
+            no_upstream_check: false,
+            all_branches: false,
+            all_statuses: false,
+            dirty_for: None,
+            stale_days: None,
+            diff_stat: false,
+            max_diff_lines: DEFAULT_MAX_DIFF_LINES,
+        }
+    }
+}
+
+/// Everything found in a repository's working tree and branches.
+#[derive(Debug, Default)]
+pub struct RepoReport {
+    /// The repository's working tree, or its git directory when it is bare.
+    pub path: PathBuf,
+    /// The most important of `statuses`, `None` when the repository is clean.
+    pub status: Option<GitStatus>,
+    /// Everything worth reporting about the repository, most important first,
+    /// an empty list meaning it is clean. Only the first one unless every
+    /// status was asked for, followed by `Stale` when it applies.
+    pub statuses: Vec<GitStatus>,
+    /// The checked out branch, `None` when HEAD is detached or unborn.
+    pub branch: Option<String>,
+    /// The upstream of the checked out branch, e.g. `origin/main`, `None`
+    /// without one or when upstreams weren't checked.
+    pub upstream: Option<String>,
+    /// How many commits the checked out branch is ahead of its upstream.
+    pub ahead: usize,
+    /// How many commits the checked out branch is behind its upstream.
+    pub behind: usize,
+    pub staged_count: usize,
+    pub modified_count: usize,
+    pub untracked_count: usize,
+    pub stash_count: usize,
+    /// Where `origin` points to.
+    pub remote_url: Option<String>,
+    /// The host `remote_url` is on.
+    pub host: Option<String>,
+    /// Newest modification time among the changed files, in seconds since the epoch.
+    pub dirty_since: Option<i64>,
+    /// Local branches that have an upstream, with `all_branches`.
+    pub branches: Vec<BranchStatus>,
+    /// The commit HEAD points to, `None` without commits.
+    pub last_commit: Option<CommitInfo>,
+    /// Lines changed in the working tree and index, with `diff_stat`.
+    pub diff_stat: Option<DiffStat>,
+}
+
+/// How much changed in the working tree and index, compared to HEAD.
+#[derive(Debug)]
+pub enum DiffStat {
+    Lines { insertions: usize, deletions: usize, files: usize },
+    /// More lines changed than `max_diff_lines`.
+    TooLarge,
+}
+
+/// How a local branch compares to its upstream.
+#[derive(Debug, Serialize)]
+pub struct BranchStatus {
+    pub name: String,
+    pub ahead: usize,
+    pub behind: usize,
+}
+
+/// Summary of a commit.
+#[derive(Debug, Serialize)]
+pub struct CommitInfo {
+    pub short_id: String,
+    pub summary: String,
+    pub author: String,
+    /// Commit time in seconds since the epoch.
+    pub time: i64,
+}
+
+/// Why [`check_path`] could not check a repository.
+#[derive(Debug)]
+pub enum GgsError {
+    Git(git2::Error),
+    Io(io::Error),
+    NotAGitRepo(PathBuf),
+}
+
+impl fmt::Display for GgsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GgsError::Git(error) => write!(f, "{}", error.message()),
+            GgsError::Io(error) => write!(f, "{}", error),
+            GgsError::NotAGitRepo(path) => write!(f, "{} is not a git repository", path.display()),
+        }
+    }
+}
+
+impl std::error::Error for GgsError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            GgsError::Git(error) => Some(error),
+            GgsError::Io(error) => Some(error),
+            GgsError::NotAGitRepo(_) => None,
+        }
+    }
+}
+
+impl From<git2::Error> for GgsError {
+    fn from(error: git2::Error) -> GgsError {
+        GgsError::Git(error)
+    }
+}
+
+impl From<io::Error> for GgsError {
+    fn from(error: io::Error) -> GgsError {
+        GgsError::Io(error)
+    }
+}
+
+/// Checks the repository at `path` the way `ggs` does without any flags.
+pub fn check_path(path: &Path) -> Result<RepoReport, GgsError> {
+    check_path_with(path, &CheckOptions::default())
+}
+
+/// Everything that applies to the repository at `path`: every status rather
+/// than only the most important one, its branch and how far it is from its
+/// upstream. A directory that isn't a repository is [`GgsError::NotAGitRepo`],
+/// any other error means the repository couldn't be checked.
+pub fn status_of(path: &Path) -> Result<RepoReport, GgsError> {
+    check_path_with(path, &CheckOptions { all_statuses: true, ..CheckOptions::default() })
+}
+
+/// Checks the repository at `path` with the given options.
+pub fn check_path_with(path: &Path, options: &CheckOptions) -> Result<RepoReport, GgsError> {
+    let path = std::fs::canonicalize(path)?;
+    let repo = open_repository(&path)?;
+
+    Ok(check_status(&repo, options)?)
+}
+
+/// Opens the repository at `path`, for checks of its own before handing it
+/// to [`check_status`]. A directory that isn't a repository is
+/// [`GgsError::NotAGitRepo`].
+pub fn open_repository(path: &Path) -> Result<Repository, GgsError> {
+    match Repository::open(path) {
+        Ok(repo) => Ok(repo),
+        Err(error) if error.code() == ErrorCode::NotFound => Err(GgsError::NotAGitRepo(path.to_path_buf())),
+        Err(error) => Err(GgsError::Git(error)),
+    }
+}
+
+/// A repository found by [`scan_directory`], with what checking it returned.
+pub type Scanned = (PathBuf, Result<RepoReport, GgsError>);
+
+/// Checks every directory directly under `path`, in parallel, sorted by path.
+/// Directories that aren't repositories are left out.
+pub fn scan_directory(path: &Path, options: &CheckOptions) -> io::Result<Vec<Scanned>> {
+    let mut directories = Vec::new();
+    for entry in std::fs::read_dir(path)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            directories.push(entry.path());
+        }
+    }
+
+    let results = directories.into_par_iter()
+        .map(|directory| {
+            let result = check_path_with(&directory, options);
+            (directory, result)
+        })
+        .collect();
+    Ok(repositories_only(results))
+}
+
+/// [`scan_directory`] for async code: the directory is listed with
+/// `tokio::fs` and every repository is checked in a blocking task, so many
+/// more checks than there are CPUs can wait on the disk at once.
+#[cfg(feature = "tokio")]
+pub async fn scan_directory_async(path: &Path, options: &CheckOptions) -> io::Result<Vec<Scanned>> {
+    let mut entries = tokio::fs::read_dir(path).await?;
+    let mut tasks = Vec::new();
+    while let Some(entry) = entries.next_entry().await? {
+        if !entry.file_type().await?.is_dir() {
+            continue;
+        }
+        let directory = entry.path();
+        let options = options.clone();
+        tasks.push(tokio::task::spawn_blocking(move || {
+            let result = check_path_with(&directory, &options);
+            (directory, result)
+        }));
+    }
+
+    let mut results = Vec::with_capacity(tasks.len());
+    for task in tasks {
+        // A task only fails when the check panicked.
+        results.push(task.await.unwrap_or_else(|error| std::panic::resume_unwind(error.into_panic())));
+    }
+    Ok(repositories_only(results))
+}
+
+fn repositories_only(mut results: Vec<Scanned>) -> Vec<Scanned> {
+    results.retain(|(_, result)| !matches!(result, Err(GgsError::NotAGitRepo(_))));
+    results.sort_by(|(a, _), (b, _)| a.cmp(b));
+    results
+}
+
+/// Checks the working tree, index and branches of `repo`.
+pub fn check_status(repo: &Repository, options: &CheckOptions) -> Result<RepoReport, Error> {
+    // Without a trailing separator, as the path was most likely given.
+    let path: PathBuf = repo.workdir().unwrap_or(repo.path()).components().collect();
+    let mut report = RepoReport {
+        path,
+        branch: current_branch(repo),
+        remote_url: origin_url(repo),
+        last_commit: last_commit_info(repo),
+        stash_count: repo.reflog("refs/stash").map(|reflog| reflog.len()).unwrap_or(0),
+        ..RepoReport::default()
+    };
+    report.host = report.remote_url.as_deref().and_then(remote_host);
+    trace!("{}: checking", report.path.display());
+    if repo.is_bare() {
+        trace!("{}: bare", report.path.display());
+        report.status = Some(GitStatus::BareRepo);
+        report.statuses = vec![GitStatus::BareRepo];
+        return Ok(report);
+    }
+
+    let mut opts = StatusOptions::new();
+    opts.show(git2::StatusShow::IndexAndWorkdir);
+    opts.include_untracked(true);
+    opts.recurse_untracked_dirs(true);
+    // Ignored files are left out by default, the way git leaves them out:
+    // libgit2 reads `.gitignore`, `.git/info/exclude`, `core.excludesFile`
+    // from any config level and, when that isn't set, `$XDG_CONFIG_HOME/git/ignore`.
+
+    // An IDE or background fetch may briefly hold the index lock, so give it a
+    // few chances to be released before reporting the repository as locked.
+    let mut delay = options.retry_delay;
+    let mut attempt = 0;
+    let statuses = loop {
+        match repo.statuses(Some(&mut opts)) {
+            Ok(statuses) => break statuses,
+            Err(error) if error.code() == ErrorCode::Locked && attempt < options.retries => {
+                attempt += 1;
+                trace!("{}: index locked, retrying in {:?}", report.path.display(), delay);
+                thread::sleep(delay);
+                delay *= 2;
+            }
+            Err(error) => return Err(error),
+        }
+    };
+
+    // Ordered from most to least important; unless every status was asked
+    // for, only the first one found is reported.
+    let mut found: Vec<GitStatus> = Vec::new();
+
+    let operation = operation_in_progress(repo.state());
+    trace!("{}: operation in progress: {:?}", report.path.display(), operation);
+    if let Some(operation) = operation {
+        found.push(GitStatus::InProgress(operation));
+    }
+
+    if let Err(error) = repo.head() {
+        trace!("{}: no HEAD: {}", report.path.display(), error.message());
+        if error.code() == ErrorCode::UnbornBranch {
+            found.push(GitStatus::Empty);
+        }
+    }
+
+    // Resolving upstreams is the most expensive check after the status itself.
+    let push_state = if options.no_upstream_check { PushState::Unknown } else { push_state(repo) };
+    let branches = if options.all_branches && !options.no_upstream_check { tracked_branches(repo)? } else { Vec::new() };
+    if let PushState::Tracking { upstream, ahead, behind } = &push_state {
+        report.upstream = Some(upstream.clone());
+        report.ahead = *ahead;
+        report.behind = *behind;
+    }
+    trace!("{}: {} ahead, {} behind, {} tracked branches", report.path.display(), report.ahead, report.behind, branches.len());
+    // Any difference from the upstream counts, as it always has.
+    if report.ahead > 0 || report.behind > 0 || branches.iter().any(|branch| branch.ahead > 0) {
+        found.push(GitStatus::UnpushedCommits);
+    }
+
+    report.staged_count = statuses.iter().filter(|entry| entry.status().intersects(STAGED_FLAGS)).count();
+    if report.staged_count > 0 {
+        found.push(GitStatus::Staged);
+    }
+
+    report.modified_count = statuses.iter().filter(|entry| entry.status().intersects(MODIFIED_FLAGS)).count();
+    if report.modified_count > 0 {
+        found.push(GitStatus::Modified);
+    }
+
+    report.untracked_count = statuses.iter().filter(|entry| entry.status().contains(git2::Status::WT_NEW)).count();
+    trace!(
+        "{}: {} staged, {} modified, {} untracked",
+        report.path.display(), report.staged_count, report.modified_count, report.untracked_count,
+    );
+
+    if options.diff_stat && (report.staged_count > 0 || report.modified_count > 0) {
+        report.diff_stat = Some(diff_stat(repo, options.max_diff_lines)?);
+    }
+
+    // How long the changes have been sitting there: the newest modification
+    // time among the changed files. Deleted files have none, so they count
+    // as changed when the last commit was made.
+    let mut dirty_since: Option<i64> = None;
+    let workdir = repo.workdir();
+    for entry in statuses.iter().filter(|entry| entry.status().intersects(STAGED_FLAGS | MODIFIED_FLAGS)) {
+        let modified_at = match (workdir, entry.path()) {
+            (Some(workdir), Some(path)) => modification_time(&workdir.join(path)),
+            _ => None,
+        };
+        dirty_since = dirty_since.max(modified_at.or_else(|| report.last_commit.as_ref().map(|commit| commit.time)));
+    }
+
+    if matches!(push_state, PushState::UpstreamGone) {
+        found.push(GitStatus::UpstreamGone);
+    }
+    if matches!(push_state, PushState::NoUpstream) && !options.ignore_no_upstream {
+        found.push(GitStatus::NoUpstream);
+    }
+    // Changes younger than --dirty-for don't count yet. Left out before
+    // picking the most important status, so what else applies still shows.
+    if let Some(days) = options.dirty_for {
+        let recent = dirty_since.is_some_and(|since| unix_now() - since <= days as i64 * SECONDS_PER_DAY);
+        if recent {
+            found.retain(|status| !matches!(status, GitStatus::Modified | GitStatus::Staged));
+        }
+    }
+
+    trace!("{}: found {:?}", report.path.display(), found);
+    if !options.all_statuses {
+        found.truncate(1);
+    }
+    // Reported whatever else applies, so old repositories show up as stale
+    // however dirty they are.
+    if let Some(days) = options.stale_days {
+        let age = report.last_commit.as_ref().map(|commit| unix_now() - commit.time);
+        if age.is_none_or(|age| age > days as i64 * SECONDS_PER_DAY) {
+            found.push(GitStatus::Stale);
+        }
+    }
+
+    report.status = found.first().cloned();
+    report.statuses = found;
+    report.dirty_since = dirty_since;
+    report.branches = branches;
+
+    Ok(report)
+}
+
+/// Name of the checked out branch, `None` when HEAD is detached or unborn.
+pub fn current_branch(repo: &Repository) -> Option<String> {
+    let head = repo.head().ok()?;
+    if !head.is_branch() {
+        return None;
+    }
+
+    head.shorthand().map(String::from)
+}
+
+/// Where the `origin` remote points to, `None` without one.
+pub fn origin_url(repo: &Repository) -> Option<String> {
+    let remote = repo.find_remote("origin").ok()?;
+    remote.url().map(String::from)
+}
+
+/// The host in a remote URL, lowercased, in either the URL form
+/// (`https://host/org/repo`, `ssh://user@host:22/org/repo`) or the scp-like
+/// form (`user@host:org/repo`). `None` for local paths.
+pub fn remote_host(url: &str) -> Option<String> {
+    let rest = match url.split_once("://") {
+        Some((_, rest)) => rest,
+        // Unlike a path, the part before the colon has no slash, and isn't a
+        // Windows drive letter.
+        None => match url.split_once(':') {
+            Some((before, _)) if before.len() > 1 && !before.contains(['/', '\\']) => before,
+            _ => return None,
+        },
+    };
+    let authority = rest.split('/').next().unwrap_or_default();
+    let host_and_port = authority.rsplit_once('@').map_or(authority, |(_, host)| host);
+    let host = match host_and_port.strip_prefix('[') {
+        Some(ipv6) => ipv6.split(']').next().unwrap_or_default(),
+        None => host_and_port.split(':').next().unwrap_or_default(),
+    };
+
+    if host.is_empty() {
+        None
+    } else {
+        Some(host.to_lowercase())
+    }
+}
+
+/// The user, organization or group a remote repository belongs to: its path
+/// without the repository name, e.g. `acme` for `git@github.com:acme/app.git`.
+/// `None` for local paths and remotes directly under the host.
+pub fn remote_owner(url: &str) -> Option<String> {
+    remote_host(url)?;
+    let path = match url.split_once("://") {
+        Some((_, rest)) => rest.split_once('/')?.1,
+        None => url.split_once(':')?.1,
+    };
+    let path = path.trim_matches('/').trim_end_matches(".git");
+    match path.rsplit_once('/') {
+        Some((owner, _)) if !owner.is_empty() => Some(String::from(owner)),
+        _ => None,
+    }
+}
+
+/// The commit HEAD points to, `None` without commits.
+pub fn last_commit_info(repo: &Repository) -> Option<CommitInfo> {
+    let commit = repo.head().ok()?.peel_to_commit().ok()?;
+    let short_id = commit.as_object().short_id().ok()?;
+    let author = commit.author();
+
+    Some(CommitInfo {
+        short_id: String::from(short_id.as_str().unwrap_or_default()),
+        summary: String::from(commit.summary().unwrap_or_default()),
+        author: String::from(author.name().unwrap_or_default()),
+        time: commit.time().seconds(),
+    })
+}
+
+/// Counts the lines changed from HEAD to the index and from the index to the
+/// working tree, stopping once there are more than `max_lines`.
+fn diff_stat(repo: &Repository, max_lines: usize) -> Result<DiffStat, Error> {
+    let head_tree = match repo.head() {
+        Ok(head) => Some(head.peel_to_tree()?),
+        Err(error) if error.code() == ErrorCode::UnbornBranch => None,
+        Err(error) => return Err(error),
+    };
+    let staged = repo.diff_tree_to_index(head_tree.as_ref(), None, None)?;
+    let unstaged = repo.diff_index_to_workdir(None, None)?;
+
+    let (mut insertions, mut deletions) = (0, 0);
+    let mut files: HashSet<PathBuf> = HashSet::new();
+    for diff in [&staged, &unstaged] {
+        let mut file_cb = |delta: git2::DiffDelta, _: f32| {
+            if let Some(path) = delta.new_file().path().or(delta.old_file().path()) {
+                files.insert(path.to_path_buf());
+            }
+            true
+        };
+        // Returning false aborts the diff, which then reports a user error.
+        let mut line_cb = |_: git2::DiffDelta, _: Option<git2::DiffHunk>, line: git2::DiffLine| {
+            match line.origin() {
+                '+' => insertions += 1,
+                '-' => deletions += 1,
+                _ => {}
+            }
+            insertions + deletions <= max_lines
+        };
+        match diff.foreach(&mut file_cb, None, None, Some(&mut line_cb)) {
+            Ok(()) => {}
+            Err(error) if error.code() == ErrorCode::User => return Ok(DiffStat::TooLarge),
+            Err(error) => return Err(error),
+        }
+    }
+
+    Ok(DiffStat::Lines { insertions, deletions, files: files.len() })
+}
+
+/// Every local branch with an upstream and how far apart they are, for `--all-branches`.
+fn tracked_branches(repo: &Repository) -> Result<Vec<BranchStatus>, Error> {
+    let mut branches = Vec::new();
+    for branch in repo.branches(Some(BranchType::Local))? {
+        let (branch, _) = branch?;
+        let upstream = match branch.upstream() {
+            Ok(upstream) => upstream,
+            Err(error) if error.code() == ErrorCode::NotFound => continue,
+            Err(error) => return Err(error),
+        };
+        let (local_oid, upstream_oid) = match (branch.get().target(), upstream.get().target()) {
+            (Some(local_oid), Some(upstream_oid)) => (local_oid, upstream_oid),
+            _ => continue,
+        };
+        let (ahead, behind) = repo.graph_ahead_behind(local_oid, upstream_oid)?;
+        if let Some(name) = branch.name()? {
+            branches.push(BranchStatus { name: String::from(name), ahead, behind });
+        }
+    }
+
+    Ok(branches)
+}
+
+/// The name of the operation a repository in `state` is in the middle of.
+fn operation_in_progress(state: RepositoryState) -> Option<&'static str> {
+    match state {
+        RepositoryState::Clean => None,
+        RepositoryState::Merge => Some("merge"),
+        RepositoryState::Revert | RepositoryState::RevertSequence => Some("revert"),
+        RepositoryState::CherryPick | RepositoryState::CherryPickSequence => Some("cherry-pick"),
+        RepositoryState::Bisect => Some("bisect"),
+        RepositoryState::Rebase | RepositoryState::RebaseInteractive | RepositoryState::RebaseMerge => Some("rebase"),
+        RepositoryState::ApplyMailbox => Some("am"),
+        RepositoryState::ApplyMailboxOrRebase => Some("am or rebase"),
+    }
+}
+
+/// Compares the checked out branch with its upstream.
+fn push_state(repo: &Repository) -> PushState {
+    let path: PathBuf = repo.workdir().unwrap_or(repo.path()).components().collect();
+    let path = path.display();
+    let head = match repo.head() {
+        Ok(head) => head,
+        Err(error) => {
+            trace!("{}: upstream unknown, no HEAD: {}", path, error.message());
+            return PushState::Unknown;
+        }
+    };
+
+    let branch_name = match head.shorthand() {
+        Some(name) => name,
+        None => {
+            trace!("{}: upstream unknown, HEAD has no name", path);
+            return PushState::Unknown;
+        }
+    };
+
+    let local_branch = match repo.find_branch(branch_name, BranchType::Local) {
+        Ok(branch) => branch,
+        Err(error) => {
+            trace!("{}: upstream unknown, no local branch {}: {}", path, branch_name, error.message());
+            return PushState::Unknown;
+        }
+    };
+
+    let local_ref = local_branch.get().name().unwrap_or("");
+    let upstream_branch = match local_branch.upstream() {
+        Ok(branch) => branch,
+        // Still configured, but `git fetch --prune` removed the remote-tracking
+        // branch after it was deleted on the remote.
+        Err(error) if error.code() == ErrorCode::NotFound && repo.branch_upstream_name(local_ref).is_ok() => {
+            trace!("{}: {} has an upstream configured, but its remote-tracking branch is gone", path, local_ref);
+            return PushState::UpstreamGone;
+        }
+        Err(error) if error.code() == ErrorCode::NotFound => {
+            trace!("{}: {} has no upstream", path, local_ref);
+            return PushState::NoUpstream;
+        }
+        Err(error) => {
+            trace!("{}: upstream of {} unknown: {}", path, local_ref, error.message());
+            return PushState::Unknown;
+        }
+    };
+    let upstream_ref = upstream_branch.get().name().unwrap_or("");
+    trace!("{}: {} tracks {}", path, local_ref, upstream_ref);
+
+    let local_oid = match repo.refname_to_id(local_ref) {
+        Ok(oid) => oid,
+        Err(error) => {
+            trace!("{}: {} not resolved: {}", path, local_ref, error.message());
+            return PushState::Unknown;
+        }
+    };
+
+    let upstream_oid = match repo.refname_to_id(upstream_ref) {
+        Ok(oid) => oid,
+        Err(error) => {
+            trace!("{}: {} not resolved: {}", path, upstream_ref, error.message());
+            return PushState::Unknown;
+        }
+    };
+
+    match repo.graph_ahead_behind(local_oid, upstream_oid) {
+        Ok((ahead, behind)) => {
+            trace!("{}: {} is {} ahead, {} behind {}", path, local_ref, ahead, behind, upstream_ref);
+            PushState::Tracking {
+                upstream: String::from(upstream_branch.name().ok().flatten().unwrap_or_default()),
+                ahead,
+                behind,
+            }
+        }
+        Err(error) => {
+            trace!("{}: {} and {} not compared: {}", path, local_ref, upstream_ref, error.message());
+            PushState::Unknown
+        }
+    }
+}
+
+/// When `path` was last modified, in seconds since the epoch.
+pub fn modification_time(path: &Path) -> Option<i64> {
+    let modified = std::fs::metadata(path).ok()?.modified().ok()?;
+    let since_epoch = modified.duration_since(UNIX_EPOCH).ok()?;
+    Some(since_epoch.as_secs() as i64)
+}
+
+/// Seconds since the epoch.
+pub fn unix_now() -> i64 {
+    match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(duration) => duration.as_secs() as i64,
+        Err(_) => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use git2::{IndexAddOption, Signature};
+    use tempfile::TempDir;
+
+    /// A repository with `file.txt` committed, in a directory removed on drop.
+    fn committed_repository() -> (TempDir, Repository) {
+        let dir = TempDir::new().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        std::fs::write(dir.path().join("file.txt"), "content\n").unwrap();
+        commit_all(&repo);
+        (dir, repo)
+    }
+
+    fn commit_all(repo: &Repository) {
+        let mut index = repo.index().unwrap();
+        index.add_all(["*"], IndexAddOption::DEFAULT, None).unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = Signature::now("ggs", "ggs@example.com").unwrap();
+        let parent = repo.head().ok().and_then(|head| head.peel_to_commit().ok());
+        let parents: Vec<&git2::Commit> = parent.iter().collect();
+        repo.commit(Some("HEAD"), &signature, &signature, "commit", &tree, &parents).unwrap();
+    }
+
+    /// Every status, without the missing upstream every test repository has.
+    fn options() -> CheckOptions {
+        CheckOptions { all_statuses: true, ignore_no_upstream: true, ..CheckOptions::default() }
+    }
+
+    #[test]
+    fn files_in_global_core_excludes_file_are_ignored() {
+        // A global config of its own, setting nothing but `core.excludesFile`.
+        let global = TempDir::new().unwrap();
+        let excludes_file = global.path().join("ignore");
+        std::fs::write(&excludes_file, "*.swp\n").unwrap();
+        std::fs::write(global.path().join(".gitconfig"), format!("[core]\n\texcludesFile = {}\n", excludes_file.display())).unwrap();
+        // SAFETY: no other test depends on the global config.
+        unsafe { git2::opts::set_search_path(git2::ConfigLevel::Global, global.path()).unwrap() };
+
+        let (dir, repo) = committed_repository();
+        std::fs::write(dir.path().join("x.swp"), "swap\n").unwrap();
+        assert!(repo.config().unwrap().snapshot().unwrap().get_path("core.excludesFile").is_ok());
+        assert!(repo.config().unwrap().open_level(git2::ConfigLevel::Local).unwrap().get_path("core.excludesFile").is_err());
+
+        let report = check_status(&repo, &options()).unwrap();
+        assert_eq!(report.statuses, Vec::new());
+        assert_eq!(report.untracked_count, 0);
+    }
+
+    #[test]
+    fn recent_changes_leave_other_statuses_with_dirty_for() {
+        let (dir, repo) = committed_repository();
+        std::fs::write(dir.path().join("file.txt"), "changed\n").unwrap();
+
+        let options = CheckOptions { dirty_for: Some(5), ..CheckOptions::default() };
+        let report = check_status(&repo, &options).unwrap();
+        assert_eq!(report.statuses, vec![GitStatus::NoUpstream]);
+    }
+
+    #[test]
+    fn stale_is_kept_along_with_the_most_important_status() {
+        // Without any commit, so stale however few days are given.
+        let dir = TempDir::new().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        std::fs::write(dir.path().join("file.txt"), "content\n").unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("file.txt")).unwrap();
+        index.write().unwrap();
+
+        let options = CheckOptions { stale_days: Some(90), ignore_no_upstream: true, ..CheckOptions::default() };
+        let report = check_status(&repo, &options).unwrap();
+        assert_eq!(report.statuses, vec![GitStatus::Empty, GitStatus::Stale]);
+        assert_eq!(report.status, Some(GitStatus::Empty));
+    }
+
+    #[test]
+    fn stale_and_upstream_statuses_are_not_dirty() {
+        let dirty: Vec<GitStatus> = [
+            GitStatus::InProgress("merge"), GitStatus::Modified, GitStatus::Staged, GitStatus::UnpushedCommits,
+            GitStatus::UpstreamGone, GitStatus::NoUpstream, GitStatus::Stale, GitStatus::Empty, GitStatus::BareRepo,
+        ].into_iter().filter(GitStatus::is_dirty).collect();
+        assert_eq!(dirty, vec![GitStatus::InProgress("merge"), GitStatus::Modified, GitStatus::Staged, GitStatus::UnpushedCommits]);
+    }
+
+    #[test]
+    fn staged_rename_is_staged() {
+        let (dir, repo) = committed_repository();
+        std::fs::rename(dir.path().join("file.txt"), dir.path().join("renamed.txt")).unwrap();
+        let mut index = repo.index().unwrap();
+        index.remove_path(Path::new("file.txt")).unwrap();
+        index.add_path(Path::new("renamed.txt")).unwrap();
+        index.write().unwrap();
+
+        let report = check_status(&repo, &options()).unwrap();
+        assert_eq!(report.statuses, vec![GitStatus::Staged]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn typechanges_are_staged_or_modified() {
+        let (dir, repo) = committed_repository();
+        std::fs::write(dir.path().join("other.txt"), "other\n").unwrap();
+        commit_all(&repo);
+
+        // A file replaced by a symlink, once in the index and once only in the working tree.
+        for name in ["file.txt", "other.txt"] {
+            std::fs::remove_file(dir.path().join(name)).unwrap();
+            std::os::unix::fs::symlink("elsewhere", dir.path().join(name)).unwrap();
+        }
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("file.txt")).unwrap();
+        index.write().unwrap();
+
+        let report = check_status(&repo, &options()).unwrap();
+        assert_eq!(report.statuses, vec![GitStatus::Staged, GitStatus::Modified]);
+        assert_eq!(report.staged_count, 1);
+        assert_eq!(report.modified_count, 1);
+    }
+}
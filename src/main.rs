@@ -1,223 +1,2854 @@
+mod config;
+mod error;
+mod import;
+mod mounts;
+#[cfg(feature = "notify")]
+mod notify;
+mod output;
+
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::env;
-use std::io::Error as IOError;
-use std::io::{ErrorKind, Write};
+use std::ffi::OsStr;
+use std::io::{self, BufRead, ErrorKind, IsTerminal, Write};
 use std::path::{Path, PathBuf};
 use std::process::exit;
+use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(feature = "tokio")]
+use std::sync::Arc;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use git2::{ConfigLevel, DescribeFormatOptions, DescribeOptions, Repository, Error, ErrorCode};
+use glob::Pattern;
+use globset::{Glob, GlobMatcher};
+#[cfg(not(feature = "tokio"))]
+use rayon::prelude::*;
+use serde::Serialize;
+use tracing::{debug, trace};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::EnvFilter;
 
-use git2::{Repository, StatusOptions, Error};
+use git_global_status::{check_status, current_branch, open_repository, modification_time, origin_url, remote_host, remote_owner, unix_now, BranchStatus, CheckOptions, CommitInfo, DiffStat, GgsError, GitStatus, RepoReport, SECONDS_PER_DAY};
+use error::AppError;
+use import::{read_import, ImportFormat};
+use mounts::Mounts;
+use output::{humanize_duration, print_groups, render_csv, render_markdown, print_status, terminal_width, write_file_atomic, write_report, Layout, PagerMode, Template};
+use config::{expand_path, load_config, load_registry, register, save_registry, set_default_directory, unregister, Config, IdentityRule, Maintenance, Registered, Root, CONFIG_ENV, DEFAULT_PROFILE, DIR_ENV, MAX_THREADS};
 
-const USAGE: &str = "Usage: ggs [-d] <input>";
+const USAGE: &str = "Usage: ggs add <path> | remove <path> | list | prune [-y|--yes] [--dry-run] | import <mr|gita> <file>\n       ggs [check] [-h] [-V] [-d] [--profile <name>] [--verbose] [-q|--quiet] [--debug|--log-level <level>] [--exclude <glob>]... [--on-branch <glob>] [--not-on-branch <glob>] [--host <glob> [--any-remote]] [--hidden|--no-hidden] [--follow-symlinks] [--include-network] [--one-file-system] [--include-bare] [--include-unsafe] [--retries <n>] [--retry-delay <ms>] [--no-retry] [--ignore-no-upstream] [--since-tag] [--show-last-commit] [--no-upstream-check] [--all-branches] [--base <ref>] [--default-branch [--remote <name>]] [--fail-fast] [--exit-code] [--only-repos] [--no-skip-markers] [--no-submodule-dedup] [-j|--jobs <n>] [--timeout <secs>] [--identity-check] [--maintenance] [--all-statuses] [--group-by <status|repo|org>] [--newest-first] [--count] [--count-by <category>] [--pager|--no-pager] [--short] [--json|--ndjson|--format <text|json|jsonl|markdown|csv>|--template <format>|-0|--print0] [--all] [--no-headings] [--bullet <prefix>] [--stale-days <days>|--no-stale] [--not-fetched-since <days>] [-o <file>] [--dirty-for <days>] [--watch <secs> [--notify]] [-f|--from-file|--repos-from <file>|-] [--fail-on <problems>] <input>... | - | --stdin0";
 const ALL_GOOD: &str = "All good!";
+/// The `--group-by org` heading for repositories without an `origin` to tell the owner from.
+const NO_REMOTE_GROUP: &str = "(no remote)";
+const IN_PROGRESS_MSG: &str = "Operations in progress:";
 const UNPUSHED_COMMITS_MSG: &str = "Directories with unpushed commits:";
 const STAGED_CHANGES_MSG: &str = "Directories with staged changes:";
 const MODIFIED_FILES_MSG: &str = "Directories with modified files:";
+const UPSTREAM_GONE_MSG: &str = "Upstream branch gone:";
+const NO_UPSTREAM_MSG: &str = "Directories with no remote tracking branch:";
+const EMPTY_MSG: &str = "Directories with empty repositories (no commits):";
+const BARE_MSG: &str = "Bare repositories:";
+const BARE_SKIPPED_MSG: &str = "Bare repositories (skipped):";
+const DEFAULT_BRANCH_DRIFT_MSG: &str = "Default branch drift:";
+const UNEXPECTED_BRANCH_MSG: &str = "Repositories on an unexpected branch:";
+const UNEXPECTED_IDENTITY_MSG: &str = "Repositories committing with an unexpected identity:";
+const NOT_FETCHED_MSG: &str = "Repositories with stale remote data:";
+const NEEDS_GC_MSG: &str = "Repositories that could use git gc:";
+const MISSING_CONFIGURED_MSG: &str = "Configured repositories not found:";
+const CORRUPT_MSG: &str = "Possibly corrupt repositories:";
+const ERRORS_MSG: &str = "Repositories with errors:";
+const LOCKED_MSG: &str = "Directories with a locked index (another git process is running):";
+const TIMED_OUT_MSG: &str = "Directories that timed out:";
+const UNREADABLE_MSG: &str = "Directories that could not be read:";
+const UNREACHABLE_MSG: &str = "Unreachable repositories:";
+const INVALID_ENTRIES_MSG: &str = "Invalid entries (not repositories):";
+const NOT_A_REPOSITORY_MSG: &str = "Not a repository:";
+const NOT_OWNED_MSG: &str = "Directories skipped because they are owned by another user (use --include-unsafe to scan them):";
+
+/// The remote `--default-branch` compares with, unless `--remote` names another.
+const DEFAULT_REMOTE: &str = "origin";
+/// Lists directories a root doesn't want scanned.
+const IGNORE_FILE: &str = ".ggsignore";
+/// Repositories containing this file are not checked.
+const SKIP_MARKER: &str = ".ggs-skip";
+/// Exit code used when some repositories could not be scanned.
+const PARTIAL_SCAN_EXIT_CODE: i32 = 4;
+/// Exit code used when `--fail-fast` found a dirty repository, or with
+/// `--exit-code` when a repository has local changes.
+const DIRTY_EXIT_CODE: i32 = 2;
+/// Exit code used with `--exit-code` when repositories only have unpushed commits.
+const UNPUSHED_EXIT_CODE: i32 = 3;
+
+/// Commit messages are never shortened below this, however narrow the terminal.
+const MIN_SUMMARY_WIDTH: usize = 20;
+
+/// Repositories that couldn't be checked, for `--fail-on`.
+#[derive(Clone, Copy, PartialEq)]
+enum Problem {
+    Unreachable,
+    Locked,
+    Corrupt,
+    TimedOut,
+    NotOwned,
+}
+
+impl std::str::FromStr for Problem {
+    type Err = ();
+
+    fn from_str(name: &str) -> Result<Problem, ()> {
+        match name {
+            "unreachable" => Ok(Problem::Unreachable),
+            "locked" => Ok(Problem::Locked),
+            "corrupt" => Ok(Problem::Corrupt),
+            "timeout" => Ok(Problem::TimedOut),
+            "not-owned" => Ok(Problem::NotOwned),
+            _ => Err(()),
+        }
+    }
+}
 
-enum GitStatus {
-    NoChanges,
+/// What `--count` counts.
+#[derive(Clone, Copy)]
+enum CountBy {
+    /// Dirty repositories, see [`GitStatus::is_dirty`].
+    Dirty,
     Modified,
     Staged,
-    UnpushedCommits
+    Unpushed,
+}
+
+impl CountBy {
+    /// Whether `report` is among the repositories counted.
+    fn counts(self, report: &RepoReport) -> bool {
+        match self {
+            CountBy::Dirty => report.statuses.iter().any(GitStatus::is_dirty),
+            CountBy::Modified => report.statuses.contains(&GitStatus::Modified),
+            CountBy::Staged => report.statuses.contains(&GitStatus::Staged),
+            CountBy::Unpushed => report.statuses.contains(&GitStatus::UnpushedCommits),
+        }
+    }
+}
+
+/// How the report is organised.
+#[derive(Clone, Copy, PartialEq)]
+enum GroupBy {
+    /// A section per status, listing every repository it applies to.
+    Status,
+    /// Every repository once, with everything found in it.
+    Repo,
+    /// A section per owner of the `origin` remote, listing its repositories
+    /// with everything found in them.
+    Org,
+}
+
+/// What to scan: the repositories found in each root, and directories given
+/// one by one.
+struct Targets {
+    roots: Vec<Root>,
+    /// Repositories, or directories containing repositories.
+    repositories: Vec<String>,
+    /// Repositories from `--from-file`, which are expected to exist.
+    listed: Vec<String>,
+}
+
+impl Targets {
+    fn roots(roots: Vec<Root>) -> Targets {
+        Targets { roots, repositories: Vec::new(), listed: Vec::new() }
+    }
+}
+
+/// The directories found in a root.
+struct Listing {
+    directories: Vec<PathBuf>,
+    /// How many directories were skipped because of excludes or `.ggsignore`.
+    excluded: usize,
+    /// How many symlinks were skipped, without `--follow-symlinks`.
+    symlinks: usize,
+    /// How many directories on network filesystems were skipped, without `--include-network`.
+    network: usize,
+    /// How many directories on other filesystems than the root's were skipped, with `--one-file-system`.
+    other_filesystems: usize,
+}
+
+/// A line of a `.ggsignore` file.
+struct IgnoreRule {
+    matcher: GlobMatcher,
+    /// `!pattern`: scan matching directories after all.
+    negated: bool,
+}
+
+/// The outcome of scanning every root once.
+struct Scan {
+    /// The rendered report, or just the number asked for by `--count`.
+    report: String,
+    /// Every dirty repository with everything found in it, by how it is displayed.
+    statuses: BTreeMap<String, Vec<GitStatus>>,
+    /// Whether some repositories could not be checked.
+    partial: bool,
+    /// Whether `--fail-fast` stopped the scan at a dirty repository.
+    stopped_early: bool,
+    /// Every repository checked, with `--format csv` or `--template`.
+    reports: Vec<RepoReport>,
+}
+
+/// A repository as a line of `--ndjson` output. Times are in seconds since the epoch.
+#[derive(Serialize)]
+struct RepoRecord<'a> {
+    path: &'a Path,
+    /// How the repository is shown in the report.
+    name: &'a str,
+    /// The label of the configured root it was found under, if it has one.
+    root_label: Option<&'a str>,
+    /// The first of `statuses`, `clean` when there are none.
+    status: &'static str,
+    /// Everything found, most important first, or `timed_out`, `not_owned` or `error`.
+    statuses: Vec<&'static str>,
+    branch: Option<&'a str>,
+    operation: Option<&'static str>,
+    error: Option<String>,
+    dirty_since: Option<i64>,
+    last_commit: Option<&'a CommitInfo>,
+    last_fetch: Option<i64>,
+    remote_url: Option<&'a str>,
+    /// The host `remote_url` is on.
+    host: Option<&'a str>,
+    upstream: Option<&'a str>,
+    /// How far the checked out branch is from its upstream, when it has one.
+    ahead: Option<usize>,
+    behind: Option<usize>,
+    branches: &'a [BranchStatus],
+    ahead_of_base: Option<usize>,
+    /// With `--default-branch`, when the local default branch differs from the remote's.
+    default_branch_drift: Option<&'a BranchStatus>,
+    /// Lines changed in the working tree and index, `None` when there were too many to count.
+    insertions: Option<usize>,
+    deletions: Option<usize>,
+}
+
+impl<'a> RepoRecord<'a> {
+    fn new(path: &'a Path, name: &'a str, root_label: Option<&'a str>, statuses: Vec<&'static str>) -> RepoRecord<'a> {
+        RepoRecord {
+            path,
+            name,
+            root_label,
+            status: statuses.first().copied().unwrap_or("clean"),
+            statuses,
+            branch: None,
+            operation: None,
+            error: None,
+            dirty_since: None,
+            last_commit: None,
+            last_fetch: None,
+            remote_url: None,
+            host: None,
+            upstream: None,
+            ahead: None,
+            behind: None,
+            branches: &[],
+            ahead_of_base: None,
+            default_branch_drift: None,
+            insertions: None,
+            deletions: None,
+        }
+    }
+
+    /// The record for a finished check, `None` for directories that aren't repositories.
+    fn from_result(path: &'a Path, name: &'a str, root_label: Option<&'a str>, result: &'a Option<Result<RepoResult, AppError>>) -> Option<RepoRecord<'a>> {
+        let result = match result {
+            Some(Ok(result)) => result,
+            Some(Err(AppError::NotARepository(_))) => return None,
+            Some(Err(error)) if error.git_code() == Some(ErrorCode::Owner) => return Some(RepoRecord::new(path, name, root_label, vec!["not_owned"])),
+            Some(Err(error)) => {
+                let mut record = RepoRecord::new(path, name, root_label, vec!["error"]);
+                record.error = Some(error.to_string());
+                return Some(record);
+            }
+            None => return Some(RepoRecord::new(path, name, root_label, vec!["timed_out"])),
+        };
+
+        let mut record = RepoRecord::new(path, name, root_label, Vec::new());
+        match &result.status {
+            Ok(check) => {
+                record.statuses = check.statuses.iter().map(GitStatus::name).collect();
+                record.operation = check.statuses.iter().find_map(|status| match status {
+                    GitStatus::InProgress(operation) => Some(*operation),
+                    _ => None,
+                });
+                record.dirty_since = check.dirty_since;
+                record.branches = &check.branches;
+                record.last_commit = check.last_commit.as_ref();
+                record.remote_url = check.remote_url.as_deref();
+                record.host = check.host.as_deref();
+                record.branch = check.branch.as_deref();
+                record.upstream = check.upstream.as_deref();
+                if check.upstream.is_some() {
+                    record.ahead = Some(check.ahead);
+                    record.behind = Some(check.behind);
+                }
+                if let Some(DiffStat::Lines { insertions, deletions, .. }) = check.diff_stat {
+                    record.insertions = Some(insertions);
+                    record.deletions = Some(deletions);
+                }
+            }
+            Err(AppError::BareRepository(_)) => return None,
+            Err(error) if error.git_code() == Some(ErrorCode::Locked) => record.statuses.push("locked"),
+            Err(error) => {
+                record.statuses.push("error");
+                record.error = Some(error.to_string());
+            }
+        }
+        record.status = record.statuses.first().copied().unwrap_or("clean");
+        record.last_fetch = result.last_fetch;
+        record.ahead_of_base = result.ahead_of_base;
+        record.default_branch_drift = result.default_branch_drift.as_ref();
+
+        Some(record)
+    }
+}
+
+/// The whole scan as a single `--json` document. Scripts rely on the field
+/// names, so they don't change between versions.
+#[derive(Serialize)]
+struct JsonReport {
+    /// The directories that were scanned.
+    roots: Vec<String>,
+    /// When the scan started, in seconds since the epoch.
+    timestamp: i64,
+    duration_ms: u64,
+    /// A `--ndjson` record per repository.
+    repositories: Vec<serde_json::Value>,
+    summary: Summary,
+    /// Roots, repositories and listed paths that couldn't be read or checked.
+    errors: Vec<ErrorEntry>,
+}
+
+/// Something that couldn't be read or checked, in a `--json` document.
+#[derive(Serialize)]
+struct ErrorEntry {
+    /// As given, for roots and listed paths that couldn't be read.
+    path: String,
+    message: String,
+}
+
+impl ErrorEntry {
+    fn new(path: impl std::fmt::Display, message: impl std::fmt::Display) -> ErrorEntry {
+        ErrorEntry { path: path.to_string(), message: message.to_string() }
+    }
+}
+
+/// How many repositories are in each state.
+#[derive(Default, Serialize)]
+struct Summary {
+    clean: usize,
+    modified: usize,
+    staged: usize,
+    unpushed: usize,
+    in_progress: usize,
+    upstream_gone: usize,
+    no_upstream: usize,
+    stale: usize,
+    empty: usize,
+    bare: usize,
+    /// Repositories that could not be checked.
+    errors: usize,
+}
+
+impl Summary {
+    fn new(checked: &[Checked]) -> Summary {
+        let mut summary = Summary::default();
+        for checked in checked {
+            match checked.outcome() {
+                Outcome::Checked(_, report) if report.statuses.is_empty() => summary.clean += 1,
+                Outcome::Checked(_, report) => {
+                    for status in &report.statuses {
+                        let count = match status {
+                            GitStatus::InProgress(_) => &mut summary.in_progress,
+                            GitStatus::Modified => &mut summary.modified,
+                            GitStatus::Staged => &mut summary.staged,
+                            GitStatus::UnpushedCommits => &mut summary.unpushed,
+                            GitStatus::UpstreamGone => &mut summary.upstream_gone,
+                            GitStatus::NoUpstream => &mut summary.no_upstream,
+                            GitStatus::Stale => &mut summary.stale,
+                            GitStatus::Empty => &mut summary.empty,
+                            GitStatus::BareRepo => &mut summary.bare,
+                        };
+                        *count += 1;
+                    }
+                }
+                Outcome::Locked(_) | Outcome::Corrupt(..) | Outcome::Failed(_) | Outcome::TimedOut => summary.errors += 1,
+                Outcome::NotOwned | Outcome::BareSkipped(_) | Outcome::NotARepository => {}
+            }
+        }
+        summary
+    }
+
+    /// Such as `47 clean, 3 modified, 1 staged, 2 unpushed`, followed by the
+    /// other states any repository is in.
+    fn line(&self) -> String {
+        let mut line = format!("{} clean, {} modified, {} staged, {} unpushed", self.clean, self.modified, self.staged, self.unpushed);
+        let others = [
+            (self.in_progress, "in progress"),
+            (self.upstream_gone, "with upstream gone"),
+            (self.no_upstream, "without upstream"),
+            (self.stale, "stale"),
+            (self.empty, "empty"),
+            (self.bare, "bare"),
+        ];
+        for (count, label) in others {
+            if count > 0 {
+                line.push_str(&format!(", {} {}", count, label));
+            }
+        }
+
+        line
+    }
+}
+
+/// What was found in a single repository.
+struct RepoResult {
+    status: Result<RepoReport, AppError>,
+    /// Extra information appended to the path when the repository is listed.
+    annotation: String,
+    /// Expected and actual branch, when the repository is not on its configured branch.
+    unexpected_branch: Option<(String, String)>,
+    /// The identity found, when it does not match the configured rule.
+    unexpected_identity: Option<Identity>,
+    /// The object directory and why it needs `git gc`, when it does.
+    needs_gc: Option<(PathBuf, String)>,
+    /// How many commits HEAD has that `--base` doesn't, when there are any.
+    ahead_of_base: Option<usize>,
+    /// The local copy of the remote's default branch, when it differs from it.
+    default_branch_drift: Option<BranchStatus>,
+    /// Whether the repository has any remotes to fetch from.
+    has_remotes: bool,
+    /// When the repository last fetched, in seconds since the epoch.
+    last_fetch: Option<i64>,
+}
+
+/// What the repository's effective config says commits will be made as.
+struct Identity {
+    name: Option<String>,
+    email: Option<String>,
+    /// Whether the repository overrides the identity in its own `.git/config`.
+    local: bool,
+}
+
+/// Configured expectations for a single repository.
+#[derive(Default)]
+struct Expectations {
+    branch: Option<String>,
+    identity: Option<IdentityRule>,
+    gc_thresholds: Option<Maintenance>,
+}
+
+#[derive(Clone)]
+struct Options {
+    include_unsafe: bool,
+    hidden: bool,
+    /// Skip hidden directories, even if the config asks for them.
+    no_hidden: bool,
+    /// Check bare repositories instead of skipping them.
+    include_bare: bool,
+    /// How each repository's working tree and branches are checked.
+    check: CheckOptions,
+    /// Annotate reported repositories with the number of commits since their last tag.
+    since_tag: bool,
+    /// Give up on a repository after this long, `None`, the default, to wait indefinitely.
+    timeout: Option<Duration>,
+    /// Compare each repository's user.name/user.email with the configured identity rules.
+    identity_check: bool,
+    /// Look for repositories with too many loose objects or packs.
+    maintenance: bool,
+    /// List repositories by their last commit, newest first, instead of by path.
+    newest_first: bool,
+    group_by: GroupBy,
+    /// Only print the number of repositories in this category.
+    count: Option<CountBy>,
+    pager: PagerMode,
+    /// A file listing repositories to check.
+    from_file: Option<PathBuf>,
+    /// Which problems make the exit code nonzero.
+    fail_on: Vec<Problem>,
+    /// Also exit nonzero for local changes and unpushed commits.
+    exit_code: bool,
+    /// Read NUL separated directories from stdin.
+    stdin0: bool,
+    /// Show repositories by their directory name instead of their full path.
+    short: bool,
+    /// Print every repository as a line of JSON as soon as it is checked.
+    ndjson: bool,
+    /// Print a Markdown table of the repositories instead of the report.
+    markdown: bool,
+    /// Print the whole scan as a single JSON document instead of the report.
+    json: bool,
+    /// Print a CSV row per repository with something to report instead of the report.
+    csv: bool,
+    /// Also print clean repositories with `csv` or `template`.
+    all: bool,
+    /// Print a line per repository with something to report, in this format, instead of the report.
+    template: Option<Template>,
+    /// Only print the paths of repositories with something to report, each followed by a NUL.
+    print0: bool,
+    /// Headings and bullets of the report.
+    layout: Layout,
+    /// Also list repositories whose last commit is older than this many days.
+    stale: Option<u64>,
+    /// Don't look for stale repositories, even if the config asks to.
+    no_stale: bool,
+    /// Report repositories with remotes that were not fetched in this many days.
+    not_fetched_since: Option<u64>,
+    /// Write the report to this file instead of stdout.
+    output: Option<PathBuf>,
+    version: bool,
+    help: bool,
+    /// Append the HEAD commit to every listed repository.
+    show_last_commit: bool,
+    /// Report how far HEAD is ahead of this ref, e.g. `origin/release`.
+    base: Option<String>,
+    /// Compare the local default branch with the remote's, whichever branch is checked out.
+    default_branch: bool,
+    /// The remote whose default branch `default_branch` compares with.
+    remote: String,
+    /// Directories matching any of these are not scanned.
+    excludes: Vec<Pattern>,
+    /// Only check repositories whose current branch matches.
+    on_branch: Option<Pattern>,
+    /// Only check repositories whose current branch doesn't match.
+    not_on_branch: Option<Pattern>,
+    /// Only check repositories whose `origin` is on a matching host.
+    host: Option<Pattern>,
+    /// Match `host` against every remote instead of only `origin`.
+    any_remote: bool,
+    /// How many repositories to check at once, 0 for one per CPU.
+    jobs: Option<usize>,
+    /// Also check submodules of other repositories being checked.
+    no_submodule_dedup: bool,
+    /// Check repositories even if they contain a skip marker.
+    no_skip_markers: bool,
+    /// Stop at the first repository with something to report.
+    fail_fast: bool,
+    /// Report scanned directories that aren't repositories, and fail.
+    only_repos: bool,
+    /// Scan symlinked directories too.
+    follow_symlinks: bool,
+    /// Scan directories on network filesystems too.
+    include_network: bool,
+    /// Don't scan directories on other filesystems than their root.
+    one_file_system: bool,
+    /// Explain what was skipped and why.
+    verbose: bool,
+    /// Only print the summary line instead of the whole report.
+    quiet: bool,
+    /// How much to log to stderr, `RUST_LOG` or warnings only when not given.
+    log_level: Option<LevelFilter>,
+    /// The config profile to use, instead of `default`.
+    profile: Option<String>,
+    /// `ggs prune` changes the registry without asking first.
+    yes: bool,
+    /// `ggs prune` only shows what it would change.
+    dry_run: bool,
+    /// Keep scanning at this interval instead of exiting after one scan.
+    watch: Option<Duration>,
+    /// Send a desktop notification when a repository gets dirty while watching.
+    notify: bool,
 }
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
-    match args.as_slice() {
-        [default] => {
-            let default_directory = match get_default_directory() {
-                Ok(dir) => dir,
-                Err(_) => {
-                    println!("No defaults specified.\n{}", USAGE);
+    let mut args: Vec<String> = env::args().collect();
+    let mut options = match parse_options(&mut args) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("Error: {}.\n{}", e, USAGE);
+            exit(1);
+        }
+    };
+
+    if options.help {
+        println!(
+            "{}\n\nGrouping:\n  --group-by org  repositories under the owner of their origin, with their statuses as badges, {} last\n\nEnvironment:\n  {}  config file to use instead of the default one\n  {}     directory to scan when none is given, ahead of the config",
+            USAGE, NO_REMOTE_GROUP, CONFIG_ENV, DIR_ENV,
+        );
+        exit(0);
+    }
+
+    if options.version {
+        let (major, minor, rev) = git2::Version::get().libgit2_version();
+        println!("ggs {} (libgit2 {}.{}.{})", env!("CARGO_PKG_VERSION"), major, minor, rev);
+        exit(0);
+    }
+
+    init_logging(options.log_level);
+
+    if options.include_unsafe {
+        // SAFETY: called once on startup, before any repository is opened.
+        if let Err(e) = unsafe { git2::opts::set_verify_owner_validation(false) } {
+            eprintln!("Error: {}. Could not disable ownership checks.", e);
+            exit(1);
+        }
+    }
+
+    let mut config = match load_config() {
+        Ok(config) => config,
+        // Going on without the config would scan some other directory.
+        Err(e @ (AppError::UnsetVariable { .. } | AppError::UnknownUser { .. })) => {
+            eprintln!("Error: {} in the config.", e);
+            exit(1);
+        }
+        Err(e) => {
+            eprintln!("Warning: {}. Ignoring config.", e);
+            Config::default()
+        }
+    };
+    let profile = match options.profile.as_deref() {
+        Some(profile) => Some(profile),
+        None => Some(DEFAULT_PROFILE).filter(|default| config.profiles.contains_key(*default)),
+    };
+    if let Some(profile) = profile {
+        if let Err(e) = config.use_profile(profile) {
+            eprintln!("Error: {}.", e);
+            exit(1);
+        }
+    }
+    options.hidden = !options.no_hidden && (options.hidden || config.hidden);
+    options.include_network |= config.include_network;
+    if let Some(lines) = config.max_diff_lines {
+        options.check.max_diff_lines = lines;
+    }
+    if !options.no_stale {
+        options.check.stale_days = options.stale.or(config.stale_days);
+    }
+
+    // `ggs check` is what `ggs` does anyway.
+    if args.get(1).is_some_and(|command| command == "check") {
+        args.remove(1);
+    }
+    let mut targets = match args.as_slice() {
+        [_] if options.stdin0 => stdin_targets(b'\0'),
+        // Only the listed repositories, unless directories are given as well.
+        [_] if options.from_file.is_some() => Targets::roots(Vec::new()),
+        [_, command, path] if command == "add" => {
+            match expand_path(path).and_then(|path| register(&path)) {
+                Ok((path, true)) => println!("Added {}", path),
+                Ok((path, false)) => {
+                    eprintln!("Error: {} is already registered.", path);
+                    exit(1);
+                }
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    exit(1);
+                }
+            }
+            return;
+        }
+        [_, command, path] if command == "remove" => {
+            match unregister(path) {
+                Ok((path, true)) => println!("Removed {}", path),
+                Ok((path, false)) => {
+                    eprintln!("Error: {} is not registered.", path);
+                    exit(1);
+                }
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    exit(1);
+                }
+            }
+            return;
+        }
+        [_, command] if command == "list" => {
+            match load_registry() {
+                Ok(repositories) => {
+                    for repository in repositories.unwrap_or_default() {
+                        println!("{}", repository.path);
+                    }
+                }
+                Err(e) => {
+                    eprintln!("Error: {}", e);
                     exit(1);
                 }
+            }
+            return;
+        }
+        [_, command, format, path] if command == "import" => {
+            import(format, path);
+            return;
+        }
+        [_, command] if command == "prune" => {
+            prune(&options, &config);
+            return;
+        }
+        // Without a directory given: the one in the environment, then the
+        // registered repositories or the default directory, then the current one.
+        [_] if env::var_os(DIR_ENV).is_some_and(|dir| !dir.is_empty()) => {
+            let dir = env::var(DIR_ENV).unwrap_or_else(|_| {
+                eprintln!("Error: {} is not valid UTF-8.", DIR_ENV);
+                exit(1);
+            });
+            Targets::roots(vec![Root::new(&dir)])
+        }
+        // Registered repositories take the place of the default directory.
+        [_] => {
+            let registry = match load_registry() {
+                Ok(registry) => registry.unwrap_or_default(),
+                Err(e) => {
+                    eprintln!("Warning: {}. Ignoring the registered repositories.", e);
+                    Vec::new()
+                }
             };
-            driver(&default_directory);
+            if !registry.is_empty() {
+                let listed = registry.into_iter().map(|repository| repository.path).collect();
+                Targets { roots: Vec::new(), repositories: Vec::new(), listed }
+            } else {
+                let mut roots = config.roots();
+                if roots.is_empty() {
+                    eprintln!("No default directory configured, scanning the current directory.");
+                    roots.push(Root::new("."));
+                }
+                Targets::roots(roots)
+            }
         }
-        [default, directory] => { 
-            driver(&args[1]);
+        [_, dash] if dash == "-" => {
+            let separator = if options.stdin0 { b'\0' } else { b'\n' };
+            stdin_targets(separator)
         }
         [_, option, _] if option == &String::from("-d") => {
             match set_default_directory(&args[2]) {
-                Ok(()) => driver(&args[2]),
-                Err(e) => println!("Error: {}. Could not set default directory.", e),
+                Ok(()) => Targets::roots(vec![Root::new(&args[2])]),
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    exit(1);
+                }
             }
-        }   
+        }
+        [_, paths @ ..] if !paths.iter().any(|path| path.starts_with('-')) => {
+            Targets::roots(paths.iter().map(|path| Root::new(path)).collect())
+        }
         _ => {
-            println!("{}", USAGE);
+            eprintln!("{}", USAGE);
+            return;
+        }
+    };
+
+    if let Some(list) = &options.from_file {
+        // `-f -` reads the list from stdin.
+        let listed = if list == Path::new("-") {
+            read_paths(io::stdin().lock(), b'\n').map_err(|e| AppError::io("stdin", e))
+        } else {
+            let file = std::fs::File::open(list).map_err(|e| AppError::io(list, e));
+            file.and_then(|file| read_paths(io::BufReader::new(file), b'\n').map_err(|e| AppError::io(list, e)))
+        };
+        match listed {
+            Ok(listed) => targets.listed = listed,
+            Err(e) => {
+                eprintln!("Error: {}. Could not read the repository list.", e);
+                exit(1);
+            }
         }
     }
+
+    driver(&targets, &options, &config);
 }
 
-fn driver(path_string: &str) {
-    let path = Path::new(&path_string);
-    let directories: Vec<PathBuf> = match list_directories(&PathBuf::from(path)) {
-        Ok(dirs) => dirs,
-        Err(error) => {
-            match error.kind() {
-                ErrorKind::NotFound => println!("Directory not found."),
-                ErrorKind::PermissionDenied => println!("Permission to access directory denied."),
-                _ => println!("Error, could not read directory. Please check if given path points to a directory"),
+/// Removes the flags it recognises from `args`, leaving the positional arguments.
+fn parse_options(args: &mut Vec<String>) -> Result<Options, String> {
+    let mut options = Options {
+        include_unsafe: false,
+        hidden: false,
+        no_hidden: false,
+        include_bare: false,
+        check: CheckOptions::default(),
+        since_tag: false,
+        timeout: None,
+        identity_check: false,
+        maintenance: false,
+        newest_first: false,
+        group_by: GroupBy::Status,
+        count: None,
+        pager: PagerMode::Auto,
+        from_file: None,
+        fail_on: vec![Problem::Unreachable, Problem::Locked, Problem::Corrupt, Problem::TimedOut, Problem::NotOwned],
+        exit_code: false,
+        stdin0: false,
+        short: false,
+        ndjson: false,
+        markdown: false,
+        json: false,
+        csv: false,
+        all: false,
+        template: None,
+        print0: false,
+        layout: Layout::default(),
+        stale: None,
+        no_stale: false,
+        not_fetched_since: None,
+        output: None,
+        version: false,
+        help: false,
+        show_last_commit: false,
+        base: None,
+        default_branch: false,
+        remote: String::from(DEFAULT_REMOTE),
+        excludes: Vec::new(),
+        on_branch: None,
+        not_on_branch: None,
+        host: None,
+        any_remote: false,
+        jobs: None,
+        no_skip_markers: false,
+        no_submodule_dedup: false,
+        fail_fast: false,
+        only_repos: false,
+        follow_symlinks: false,
+        include_network: false,
+        one_file_system: false,
+        verbose: false,
+        quiet: false,
+        log_level: None,
+        yes: false,
+        dry_run: false,
+        profile: None,
+        watch: None,
+        notify: false,
+    };
+
+    let mut positional: Vec<String> = Vec::new();
+    // Whether a format was asked for, so `-o` doesn't pick one from the extension.
+    let mut format_given = false;
+    let mut iter = args.drain(..);
+    while let Some(arg) = iter.next() {
+        if matches!(arg.as_str(), "--json" | "--ndjson" | "--format" | "--template" | "-0" | "--print0") {
+            format_given = true;
+        }
+        match arg.as_str() {
+            "-V" | "--version" => options.version = true,
+            "-h" | "--help" => options.help = true,
+            "--include-unsafe" => options.include_unsafe = true,
+            "--hidden" => options.hidden = true,
+            "--no-hidden" => options.no_hidden = true,
+            "--include-bare" => options.include_bare = true,
+            "--no-retry" => options.check.retries = 0,
+            "--ignore-no-upstream" => options.check.ignore_no_upstream = true,
+            "--since-tag" => options.since_tag = true,
+            "--show-last-commit" => options.show_last_commit = true,
+            "--all-branches" => options.check.all_branches = true,
+            "--no-upstream-check" => options.check.no_upstream_check = true,
+            "--base" => options.base = Some(parse_value(&arg, iter.next())?),
+            "--exclude" => options.excludes.push(parse_value(&arg, iter.next())?),
+            "--on-branch" => options.on_branch = Some(parse_value(&arg, iter.next())?),
+            "--not-on-branch" => options.not_on_branch = Some(parse_value(&arg, iter.next())?),
+            "--host" => options.host = Some(parse_value(&arg, iter.next())?),
+            "--any-remote" => options.any_remote = true,
+            "--default-branch" => options.default_branch = true,
+            "--remote" => options.remote = parse_value(&arg, iter.next())?,
+            "--verbose" => options.verbose = true,
+            "-q" | "--quiet" => options.quiet = true,
+            "--debug" => options.log_level = Some(LevelFilter::DEBUG),
+            "--log-level" => {
+                options.log_level = match iter.next() {
+                    Some(level) => match level.parse::<LevelFilter>() {
+                        Ok(level) => Some(level),
+                        Err(_) => return Err(format!("invalid value '{}' for --log-level, expected off, error, warn, info, debug or trace", level)),
+                    },
+                    None => return Err(format!("{} requires a value", arg)),
+                };
             }
-            exit(1);
+            "--profile" => options.profile = Some(parse_value(&arg, iter.next())?),
+            "--follow-symlinks" => options.follow_symlinks = true,
+            "--include-network" => options.include_network = true,
+            "--one-file-system" => options.one_file_system = true,
+            "--fail-fast" => options.fail_fast = true,
+            "--only-repos" | "--strict" => options.only_repos = true,
+            "--exit-code" => options.exit_code = true,
+            "-y" | "--yes" => options.yes = true,
+            "--dry-run" => options.dry_run = true,
+            "--no-skip-markers" => options.no_skip_markers = true,
+            "--no-submodule-dedup" => options.no_submodule_dedup = true,
+            "--jobs" | "-j" => {
+                let jobs: usize = parse_value(&arg, iter.next())?;
+                if jobs > MAX_THREADS {
+                    return Err(format!("invalid value '{}' for {}, expected at most {}", jobs, arg, MAX_THREADS));
+                }
+                options.jobs = Some(jobs);
+            }
+            "--identity-check" => options.identity_check = true,
+            "--maintenance" => options.maintenance = true,
+            "--all-statuses" => options.check.all_statuses = true,
+            "--newest-first" => options.newest_first = true,
+            "--group-by" => {
+                options.group_by = match iter.next().as_deref() {
+                    Some("status") => GroupBy::Status,
+                    Some("repo") => GroupBy::Repo,
+                    Some("org") => GroupBy::Org,
+                    Some(other) => return Err(format!("invalid value '{}' for --group-by, expected status, repo or org", other)),
+                    None => return Err(format!("{} requires a value", arg)),
+                };
+                // Each repository is shown with everything that applies to it.
+                options.check.all_statuses |= options.group_by != GroupBy::Status;
+            }
+            "-o" | "--output" => options.output = Some(parse_value(&arg, iter.next())?),
+            "--dirty-for" => options.check.dirty_for = Some(parse_value(&arg, iter.next())?),
+            "--watch" => options.watch = Some(Duration::from_secs(parse_value(&arg, iter.next())?)),
+            "--notify" if cfg!(feature = "notify") => options.notify = true,
+            "--notify" => return Err(String::from("--notify needs ggs to be built with the `notify` feature")),
+            "--stale" | "--stale-days" => options.stale = Some(parse_value(&arg, iter.next())?),
+            "--no-stale" => options.no_stale = true,
+            "--not-fetched-since" => options.not_fetched_since = Some(parse_value(&arg, iter.next())?),
+            "--pager" => options.pager = PagerMode::Always,
+            "--no-pager" => options.pager = PagerMode::Never,
+            "--short" => options.short = true,
+            "--stdin0" => options.stdin0 = true,
+            "-f" | "--from-file" | "--repos-from" => options.from_file = Some(parse_value(&arg, iter.next())?),
+            "--fail-on" => {
+                let value: String = parse_value(&arg, iter.next())?;
+                options.fail_on = Vec::new();
+                for name in value.split(',').filter(|name| *name != "none") {
+                    match name.parse() {
+                        Ok(problem) => options.fail_on.push(problem),
+                        Err(()) => return Err(format!("invalid value '{}' for --fail-on, expected unreachable, locked, corrupt, timeout, not-owned or none", name)),
+                    }
+                }
+            }
+            "--ndjson" => options.ndjson = true,
+            "--all" => options.all = true,
+            "--json" => options.json = true,
+            "--format" => {
+                (options.ndjson, options.markdown, options.json, options.csv) = match iter.next().as_deref() {
+                    Some("text") => (false, false, false, false),
+                    Some("jsonl") | Some("ndjson") => (true, false, false, false),
+                    Some("markdown") => (false, true, false, false),
+                    Some("json") => (false, false, true, false),
+                    Some("csv") => (false, false, false, true),
+                    Some(other) => return Err(format!("invalid value '{}' for --format, expected text, json, jsonl, markdown or csv", other)),
+                    None => return Err(format!("{} requires a value", arg)),
+                };
+            }
+            "--template" => {
+                let template: String = parse_value(&arg, iter.next())?;
+                options.template = Some(Template::parse(&template).map_err(|error| format!("invalid value for --template: {}", error))?);
+            }
+            "-0" | "--print0" => options.print0 = true,
+            "--no-headings" => options.layout.headings = false,
+            "--bullet" => options.layout.bullet = parse_value(&arg, iter.next())?,
+            "--count" => options.count = options.count.or(Some(CountBy::Dirty)),
+            "--count-by" => {
+                options.count = match iter.next().as_deref() {
+                    Some("modified") => Some(CountBy::Modified),
+                    Some("staged") => Some(CountBy::Staged),
+                    Some("unpushed") => Some(CountBy::Unpushed),
+                    Some(other) => return Err(format!("invalid value '{}' for --count-by, expected modified, staged or unpushed", other)),
+                    None => return Err(format!("{} requires a value", arg)),
+                };
+                // A repository must be counted in every category it belongs to.
+                options.check.all_statuses = true;
+            }
+            "--timeout" => {
+                let secs: u64 = parse_value(&arg, iter.next())?;
+                options.timeout = if secs == 0 { None } else { Some(Duration::from_secs(secs)) };
+            }
+            "--retries" => options.check.retries = parse_value(&arg, iter.next())?,
+            "--retry-delay" => options.check.retry_delay = Duration::from_millis(parse_value(&arg, iter.next())?),
+            _ => positional.push(arg),
+        }
+    }
+    drop(iter);
+    *args = positional;
+    if !format_given && options.count.is_none() {
+        match options.output.as_deref().and_then(Path::extension).and_then(OsStr::to_str) {
+            Some("json") => options.json = true,
+            Some("csv") => options.csv = true,
+            Some("md") => options.markdown = true,
+            _ => {}
         }
+    }
+    if options.print0 && options.ndjson {
+        return Err(String::from("--print0 can't be combined with --ndjson"));
+    }
+    if options.json && (options.ndjson || options.print0 || options.count.is_some()) {
+        return Err(String::from("--json can't be combined with --ndjson, --print0 or --count"));
+    }
+    if options.csv && (options.ndjson || options.json || options.print0 || options.count.is_some()) {
+        return Err(String::from("--format csv can't be combined with --ndjson, --json, --print0 or --count"));
+    }
+    if options.template.is_some() && (options.ndjson || options.json || options.markdown || options.csv || options.print0 || options.count.is_some()) {
+        return Err(String::from("--template can't be combined with another output format, --print0 or --count"));
+    }
+    // Records are written as repositories are checked, before they could be sorted.
+    if options.newest_first && options.ndjson {
+        return Err(String::from("--newest-first can't be combined with --ndjson"));
+    }
+    let stdin_list = options.from_file.as_deref() == Some(Path::new("-"));
+    if stdin_list && (options.stdin0 || args.iter().skip(1).any(|arg| arg == "-")) {
+        return Err(String::from("--from-file - can't be combined with another list on stdin"));
+    }
+    // Only shown in these, and costly on repositories with many changes.
+    options.check.diff_stat = options.verbose || options.ndjson || options.json;
+    // The `status` column lists every status that applies.
+    if options.csv {
+        options.check.all_statuses = true;
+    }
+
+    Ok(options)
+}
+
+fn parse_value<T: std::str::FromStr>(flag: &str, value: Option<String>) -> Result<T, String> {
+    let value = match value {
+        Some(value) => value,
+        None => return Err(format!("{} requires a value", flag)),
     };
-    
-    let mut modified: Vec<String> = Vec::new();
-    let mut staged: Vec<String> = Vec::new();
-    let mut unpushed_commits: Vec<String> = Vec::new();
-    let mut no_changes: usize = 0;
 
-    for directory in &directories {
-        if let Ok(repository) = Repository::open(&directory) {
+    match value.parse() {
+        Ok(parsed) => Ok(parsed),
+        Err(_) => Err(format!("invalid value '{}' for {}", value, flag)),
+    }
+}
 
-            let path = match directory.to_str() {
-                        Some(str) => String::from(str),
-                        None => continue,
-            };
+fn stdin_targets(separator: u8) -> Targets {
+    match read_paths(io::stdin().lock(), separator) {
+        Ok(repositories) => Targets { roots: Vec::new(), repositories, listed: Vec::new() },
+        Err(e) => {
+            eprintln!("Error: {}. Could not read directories from stdin.", e);
+            exit(1);
+        }
+    }
+}
 
-            match check_status(repository) {
-                Ok(GitStatus::NoChanges) => no_changes += 1, 
-                Ok(GitStatus::Modified) => modified.push(path),
-                Ok(GitStatus::Staged) => staged.push(path),
-                Ok(GitStatus::UnpushedCommits) => unpushed_commits.push(path),
-                Err(_) => {
-                    println!("Could not check status for {}", path);
-                    continue
-                },
+/// Directories separated by `separator`. Newline separated input may have
+/// blank lines and `#` comments; NUL separated paths are taken as they are.
+fn read_paths(input: impl BufRead, separator: u8) -> io::Result<Vec<String>> {
+    let mut paths = Vec::new();
+    for entry in input.split(separator) {
+        let entry = String::from_utf8_lossy(&entry?).into_owned();
+        if separator == b'\n' {
+            let line = entry.trim();
+            if !line.is_empty() && !line.starts_with('#') {
+                paths.push(String::from(line));
             }
+        } else if !entry.is_empty() {
+            paths.push(entry);
+        }
+    }
+
+    Ok(paths)
+}
+
+/// Registers the repositories listed in `path`, a file kept for another tool.
+fn import(format: &str, path: &str) {
+    let format: ImportFormat = match format.parse() {
+        Ok(format) => format,
+        Err(()) => {
+            eprintln!("Error: invalid format '{}' for import, expected mr or gita", format);
+            exit(1);
+        }
+    };
+    let imported = match expand_path(path).and_then(|path| read_import(format, &path)) {
+        Ok(imported) => imported,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            exit(1);
         }
+    };
 
+    for warning in &imported.warnings {
+        eprintln!("Warning: {}. Skipping it.", warning);
     }
-    if no_changes == directories.len() {
-        println!("{}", ALL_GOOD);
-        exit(0);
+    for path in &imported.paths {
+        match register(path) {
+            Ok((path, true)) => println!("Added {}", path),
+            Ok((path, false)) => println!("{} is already registered", path),
+            Err(e) => eprintln!("Warning: {}. Skipping it.", e),
+        }
+    }
+}
+
+/// Drops registered repositories that are gone from the registry, or points
+/// them to where they were moved: a repository under the configured roots
+/// with the same `origin`.
+fn prune(options: &Options, config: &Config) {
+    let registry = match load_registry() {
+        Ok(registry) => registry.unwrap_or_default(),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            exit(1);
+        }
+    };
+
+    let reasons: Vec<Option<&str>> = registry.iter()
+        .map(|repository| {
+            if !Path::new(&repository.path).exists() {
+                return Some("no longer exists");
+            }
+            match open_repository(Path::new(&repository.path)) {
+                Err(GgsError::NotAGitRepo(_)) => Some("no longer a git repository"),
+                // Other errors are reported by `ggs check`, the repository is still there.
+                _ => None,
+            }
+        })
+        .collect();
+    if reasons.iter().all(Option::is_none) {
+        println!("Nothing to prune.");
+        return;
     }
 
-    print_status(&unpushed_commits, UNPUSHED_COMMITS_MSG);
-    print_status(&staged, STAGED_CHANGES_MSG);
-    print_status(&modified, MODIFIED_FILES_MSG)
+    let relocatable = registry.iter().zip(&reasons).any(|(repository, reason)| reason.is_some() && repository.origin.is_some());
+    let by_origin = if relocatable { repositories_by_origin(options, config) } else { HashMap::new() };
+
+    let mut pruned: Vec<Registered> = Vec::new();
+    for (repository, reason) in registry.iter().zip(&reasons) {
+        let reason = match reason {
+            Some(reason) => reason,
+            None => {
+                pruned.push(repository.clone());
+                continue;
+            }
+        };
+        // Repositories that are registered already can't be where this one went.
+        let found: Vec<&str> = repository.origin.as_deref()
+            .and_then(|origin| by_origin.get(remote_key(origin)))
+            .into_iter()
+            .flatten()
+            .filter_map(|path| path.to_str())
+            .filter(|path| !registry.iter().chain(&pruned).any(|registered| registered.path == *path))
+            .collect();
+        match found.as_slice() {
+            [path] => {
+                println!("Move {} to {} ({}, same origin)", repository.path, path, reason);
+                pruned.push(Registered { path: String::from(*path), origin: repository.origin.clone() });
+            }
+            [] => println!("Remove {} ({})", repository.path, reason),
+            paths => println!("Remove {} ({}, several repositories have its origin: {})", repository.path, reason, paths.join(", ")),
+        }
+    }
 
+    if options.dry_run {
+        println!("Dry run, the registry was not changed.");
+        return;
+    }
+    if !options.yes && !confirm("Update the registry?") {
+        println!("The registry was not changed.");
+        return;
+    }
+    if let Err(e) = save_registry(&pruned) {
+        eprintln!("Error: {}", e);
+        exit(1);
+    }
 }
 
-fn list_directories(path: &PathBuf) -> Result<Vec<PathBuf>,IOError>{
+/// Repositories under the configured roots, by their `origin`.
+fn repositories_by_origin(options: &Options, config: &Config) -> HashMap<String, Vec<PathBuf>> {
+    let mut excludes = options.excludes.clone();
+    excludes.extend(config.exclude_patterns());
 
-    let mut directories: Vec<PathBuf> = Vec::new();
-    for entry in path.read_dir()? {
-        if let Ok(dir) = entry {
-            if  dir.path().is_dir() {
-                directories.push(dir.path());
+    let mut by_origin: HashMap<String, Vec<PathBuf>> = HashMap::new();
+    for root in config.roots() {
+        // A root that can't be read just has no repository to offer.
+        for root_path in resolve_roots(&root.path).unwrap_or_default() {
+            let mut candidates = list_directories(&root_path, options, &excludes).map(|listing| listing.directories).unwrap_or_default();
+            candidates.push(root_path);
+            for directory in candidates {
+                let origin = match open_repository(&directory).ok().and_then(|repo| origin_url(&repo)) {
+                    Some(origin) => origin,
+                    None => continue,
+                };
+                let paths = by_origin.entry(String::from(remote_key(&origin))).or_default();
+                if !paths.contains(&directory) {
+                    paths.push(directory);
+                }
             }
         }
     }
-    
-    Ok(directories)
+
+    by_origin
 }
-    
-fn check_status(repo: Repository) -> Result<GitStatus, Error> {
 
-    let mut opts = StatusOptions::new();
-    opts.show(git2::StatusShow::IndexAndWorkdir);
-    opts.include_untracked(true);
-    opts.recurse_untracked_dirs(true);
+/// A remote URL without the parts that differ between clones of the same
+/// repository, such as a trailing `.git`.
+fn remote_key(url: &str) -> &str {
+    url.trim_end_matches('/').trim_end_matches(".git")
+}
+
+/// Asks `question` on the terminal; anything but yes is no.
+fn confirm(question: &str) -> bool {
+    print!("{} [y/N] ", question);
+    let _ = io::stdout().flush();
+    let mut answer = String::new();
+    if io::stdin().read_line(&mut answer).is_err() {
+        return false;
+    }
+
+    matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+}
 
-    let statuses = match repo.statuses(Some(&mut opts)) {
-        Ok(status) => status,
-        Err(error) => return Err(error),
+/// Logs to stderr at `level`, or as `RUST_LOG` says without one, and only
+/// warnings when neither is given. Independent of `--verbose`, which is about
+/// the report.
+fn init_logging(level: Option<LevelFilter>) {
+    let filter = match level {
+        Some(level) => EnvFilter::default().add_directive(level.into()),
+        None => EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("warn")),
     };
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(io::stderr)
+        .with_ansi(io::stderr().is_terminal())
+        .with_target(false)
+        .without_time()
+        .init();
+}
+
+fn driver(targets: &Targets, options: &Options, config: &Config) {
+    if let Some(interval) = options.watch {
+        watch(targets, options, config, interval);
+    }
 
-    for entry in statuses.iter() {
-        let status = entry.status();
+    let mut scan = scan(targets, options, config);
+    if options.csv {
+        scan.report = render_csv(scan.reports.iter().filter(|report| options.all || report.status.is_some()));
+    }
+    if let Some(template) = &options.template {
+        scan.report = scan.reports.iter()
+            .filter(|report| options.all || report.status.is_some())
+            .map(|report| format!("{}\n", template.render(report)))
+            .collect();
+    }
 
+    match &options.output {
+        // Every repository was printed as soon as it was checked.
+        None if options.ndjson => {}
+        Some(output) => {
+            if let Err(error) = write_file_atomic(output, scan.report.as_bytes()) {
+                eprintln!("Error: {}", AppError::io(output, error));
+                exit(1);
+            }
+        }
+        None if options.count.is_some() || options.print0 || options.json => print!("{}", scan.report),
+        None => write_report(&scan.report, options.pager),
+    }
 
-        if has_commits_not_pushed(&repo) {
-            return Ok(GitStatus::UnpushedCommits);
+    if scan.stopped_early {
+        exit(DIRTY_EXIT_CODE);
+    }
+    if options.exit_code {
+        let statuses = || scan.statuses.values().flatten();
+        if statuses().any(|status| matches!(status, GitStatus::InProgress(_) | GitStatus::Modified | GitStatus::Staged)) {
+            exit(DIRTY_EXIT_CODE);
+        }
+        if statuses().any(|status| *status == GitStatus::UnpushedCommits) {
+            exit(UNPUSHED_EXIT_CODE);
         }
+    }
+    if scan.partial {
+        exit(PARTIAL_SCAN_EXIT_CODE);
+    }
+}
+
+/// Scans again every `interval`, redrawing the report each time.
+fn watch(targets: &Targets, options: &Options, config: &Config, interval: Duration) -> ! {
+    #[cfg(feature = "notify")]
+    let mut notifier = notify::Notifier::new();
+    #[cfg(feature = "notify")]
+    let mut previous: Option<BTreeMap<String, Vec<GitStatus>>> = None;
+    let clear_screen = io::stdout().is_terminal();
 
-        if status.intersects(git2::Status::INDEX_NEW | git2::Status::INDEX_MODIFIED | git2::Status::INDEX_DELETED) {
-            return Ok(GitStatus::Staged);
+    loop {
+        let scan = scan(targets, options, config);
+        if clear_screen {
+            print!("\x1b[2J\x1b[H");
         }
+        print!("{}", scan.report);
+        let _ = io::stdout().flush();
 
-        if status.intersects(git2::Status::WT_MODIFIED | git2::Status::WT_DELETED) {
-            return Ok(GitStatus::Modified);
+        #[cfg(feature = "notify")]
+        if options.notify {
+            if let Some(previous) = &previous {
+                notifier.record(previous, &scan.statuses);
+            }
+            notifier.send_if_due();
+        }
+        #[cfg(feature = "notify")]
+        {
+            previous = Some(scan.statuses);
         }
+
+        thread::sleep(interval);
     }
+}
 
-    Ok(GitStatus::NoChanges)
+/// The directories a scan checks, and those left out on the way.
+struct Found {
+    /// Every directory to check, along with how it is shown in the report and
+    /// the label of the root it was found under.
+    directories: Vec<(PathBuf, String, Option<String>)>,
+    root_paths: Vec<PathBuf>,
+    /// Roots and repositories that couldn't be read, each with its path as given.
+    unreadable: Vec<(String, AppError)>,
+    /// The same for `--from-file` entries.
+    unreachable: Vec<(String, AppError)>,
+    /// `--from-file` entries, which are reported when they turn out not to be repositories.
+    listed: HashSet<PathBuf>,
+    /// Repositories that opted out with a marker file.
+    skipped: Vec<PathBuf>,
+    skipped_by_config: Vec<PathBuf>,
+    filtered_by_branch: Vec<PathBuf>,
+    filtered_by_host: Vec<PathBuf>,
+    /// How many of those filtered out by host have no remote at all.
+    without_remotes: usize,
 }
 
-fn print_status(directories: &[String], message: &str) {
-    if !directories.is_empty() {
-        println!("{}", message);
-        for directory in directories {
-            println!("  * {}", directory);
+/// A directory that was checked.
+struct Checked {
+    directory: PathBuf,
+    /// How the repository is shown in the report.
+    name: String,
+    /// The label of the root it was found under.
+    root_label: Option<String>,
+    /// `None` when the check timed out.
+    result: Option<Result<RepoResult, AppError>>,
+}
+
+/// What checking a directory came to.
+enum Outcome<'a> {
+    Checked(&'a RepoResult, &'a RepoReport),
+    /// Another git process held the index lock.
+    Locked(&'a RepoResult),
+    /// Reading the index or objects failed; holds what libgit2 said.
+    Corrupt(&'a RepoResult, String),
+    /// The repository couldn't be opened; holds why.
+    Failed(String),
+    TimedOut,
+    NotOwned,
+    /// A bare repository, without `--include-bare`; still checked for `--maintenance`.
+    BareSkipped(&'a RepoResult),
+    NotARepository,
+}
+
+impl Checked {
+    fn outcome(&self) -> Outcome<'_> {
+        let result = match &self.result {
+            Some(Ok(result)) => result,
+            Some(Err(error)) if error.git_code() == Some(ErrorCode::Owner) => return Outcome::NotOwned,
+            Some(Err(AppError::NotARepository(_))) => return Outcome::NotARepository,
+            Some(Err(error)) => {
+                let message = match error.git_error() {
+                    Some(source) => String::from(source.message()),
+                    None => error.to_string(),
+                };
+                return Outcome::Failed(message);
+            }
+            None => return Outcome::TimedOut,
+        };
+        match &result.status {
+            Ok(report) => Outcome::Checked(result, report),
+            Err(AppError::BareRepository(_)) => Outcome::BareSkipped(result),
+            Err(error) if error.git_code() == Some(ErrorCode::Locked) => Outcome::Locked(result),
+            // `git fsck` should tell more.
+            Err(error) => {
+                let detail = match error.git_error() {
+                    Some(source) => format!("{:?} error {:?}: {}", source.class(), source.code(), source.message()),
+                    None => error.to_string(),
+                };
+                Outcome::Corrupt(result, detail)
+            }
         }
     }
-}
 
+    /// What was found in the repository, when it could be checked.
+    fn report(&self) -> Option<&RepoReport> {
+        match &self.result {
+            Some(Ok(RepoResult { status: Ok(report), .. })) => Some(report),
+            _ => None,
+        }
+    }
 
-fn has_commits_not_pushed(repo: &Repository) -> bool {
-    let head = match repo.head() {
-        Ok(head) => head,
-        Err(_) => return false,
-    };
+    /// The repository as a `--ndjson` record, `None` for directories that aren't repositories.
+    fn record(&self) -> Option<RepoRecord<'_>> {
+        RepoRecord::from_result(&self.directory, &self.name, self.root_label.as_deref(), &self.result)
+    }
+}
 
-    let branch_name = match head.shorthand() {
-        Some(name) => name,
-        None => return false,
-    };
+fn scan(targets: &Targets, options: &Options, config: &Config) -> Scan {
+    let now = unix_now();
+    let started = Instant::now();
+    let found = find_directories(targets, options, config);
+    let expected_branches = config.expected_branches();
 
-    let local_branch = match repo.find_branch(branch_name, git2::BranchType::Local) {
-        Ok(branch) => branch,
-        Err(_) => return false,
-    };
+    let jobs: Vec<Job> = found.directories.iter()
+        .map(|(directory, name, root_label)| Job {
+            directory: directory.clone(),
+            name: name.clone(),
+            root_label: root_label.clone(),
+            expectations: Expectations {
+                branch: expected_branches.get(directory).cloned(),
+                identity: if options.identity_check { config.identity_rule(directory) } else { None },
+                gc_thresholds: if options.maintenance { Some(config.maintenance.clone()) } else { None },
+            },
+        })
+        .collect();
+    let mut checked: Vec<Checked> = check_repositories(jobs, options, options.jobs.or(config.threads).unwrap_or(0))
+        .into_iter()
+        .flatten()
+        .collect();
+    if options.newest_first {
+        // The sort is stable, so ties and repositories without commits stay in path order.
+        checked.sort_by_key(|checked| Reverse(checked.report().and_then(|report| report.last_commit.as_ref()).map(|commit| commit.time)));
+    }
+    // Others may have finished before the scan was stopped, they aren't reported.
+    let mut stopped_early = false;
+    if options.fail_fast {
+        if let Some(first) = checked.iter().position(|checked| is_dirty(&checked.result)) {
+            checked.truncate(first + 1);
+            stopped_early = true;
+        }
+    }
 
-    let upstream_branch = match local_branch.upstream() {
-        Ok(branch) => branch,
-        Err(_) => return false,
-    };
+    let statuses: BTreeMap<String, Vec<GitStatus>> = checked.iter()
+        .filter_map(|checked| Some((checked.name.clone(), checked.report()?.statuses.clone())))
+        .filter(|(_, statuses)| statuses.iter().any(GitStatus::is_dirty))
+        .collect();
+    let partial = is_partial(&found, &checked, options);
+    let summary = Summary::new(&checked);
 
-    let local_oid = match repo.refname_to_id(local_branch.get().name().unwrap_or("")) {
-        Ok(oid) => oid,
-        Err(_) => return false,
+    let report = if let Some(count_by) = options.count {
+        let count = checked.iter().filter_map(Checked::report).filter(|report| count_by.counts(report)).count();
+        format!("{}\n", count)
+    } else if options.ndjson {
+        // Without `-o`, every record was printed as soon as it was checked.
+        if options.output.is_some() {
+            checked.iter()
+                .filter_map(Checked::record)
+                .filter_map(|record| serde_json::to_string(&record).ok())
+                .map(|line| format!("{}\n", line))
+                .collect()
+        } else {
+            String::new()
+        }
+    } else if options.json {
+        render_json(&found, &checked, summary, now, started.elapsed())
+    } else if options.print0 {
+        checked.iter()
+            .filter(|checked| is_dirty(&checked.result))
+            .map(|checked| format!("{}\0", checked.directory.display()))
+            .collect()
+    } else if options.markdown {
+        let reports: Vec<&RepoReport> = checked.iter().filter_map(Checked::report).collect();
+        render_markdown(&reports, &found.root_paths, now, &summary.line())
+    } else {
+        render_report(&found, &checked, &expected_branches, &summary, stopped_early, options, now)
     };
 
-    let upstream_oid = match repo.refname_to_id(upstream_branch.get().name().unwrap_or("")) {
-        Ok(oid) => oid,
-        Err(_) => return false,
+    let reports = if options.csv || options.template.is_some() {
+        checked.into_iter().filter_map(|checked| checked.result?.ok()?.status.ok()).collect()
+    } else {
+        Vec::new()
     };
-
-    local_oid != upstream_oid
+    Scan {
+        report,
+        statuses,
+        partial,
+        stopped_early,
+        reports,
+    }
 }
 
-fn set_default_directory(path: &String) -> Result<(), IOError> {
- 
-    let home = match env::var("HOME") {
-        Ok(val) => val,
-        Err(e) => panic!("Couldn't read HOME environment variable ({})", e),
-    };
+/// Every directory to check under the roots and repositories given, leaving
+/// out those excluded, skipped or filtered out.
+fn find_directories(targets: &Targets, options: &Options, config: &Config) -> Found {
+    // Every directory to check, along with how it is shown in the report and
+    // the label of the root it was found under.
+    let mut directories: Vec<(PathBuf, String, Option<String>)> = Vec::new();
+    let mut root_paths: Vec<PathBuf> = Vec::new();
+    // Roots may overlap. Every directory found, with where it is in
+    // `directories` and the depth of the root it was found under, `None` when
+    // it was given on its own.
+    let mut seen: HashMap<PathBuf, (usize, Option<usize>)> = HashMap::new();
 
-    let mut config_path = PathBuf::from(home);
-    config_path.push(".config/ggs/config.txt");
+    let mut excludes = options.excludes.clone();
+    excludes.extend(config.exclude_patterns());
 
-    if let Some(dir) = config_path.parent() {
-        std::fs::create_dir_all(dir)?;
-    } 
+    // A root or repository that can't be read is reported, the others are still scanned.
+    let mut unreadable: Vec<(String, AppError)> = Vec::new();
 
-    let mut file = std::fs::File::create(&config_path)?;
-    file.write_all(path.as_bytes())?;
-    Ok(())
-}
+    for root in &targets.roots {
+        let resolved = match resolve_roots(&root.path) {
+            Ok(resolved) => resolved,
+            Err(error) => {
+                unreadable.push((root.path.clone(), error));
+                continue;
+            }
+        };
 
-fn get_default_directory() -> Result<String, IOError> {
-    let home = match env::var("HOME") {
-        Ok(val) => val,
-        Err(e) => panic!("Couldn't read HOME environment variable ({})", e),
-    };
+        for root_path in resolved {
+            if root_paths.contains(&root_path) {
+                continue;
+            }
+            let listing = match list_directories(&root_path, options, &excludes) {
+                Ok(listing) => listing,
+                Err(error) => {
+                    unreadable.push((root_path.display().to_string(), error));
+                    continue;
+                }
+            };
+            if options.verbose && listing.excluded > 0 {
+                eprintln!("Skipped {} directories in {} matching excludes", listing.excluded, root_path.display());
+            }
+            if options.verbose && listing.symlinks > 0 {
+                eprintln!("Skipped {} symlinks in {} (use --follow-symlinks to scan them)", listing.symlinks, root_path.display());
+            }
+            if listing.network > 0 {
+                eprintln!("Skipped {} directories on network filesystems (use --include-network to scan)", listing.network);
+            }
+            if options.verbose && listing.other_filesystems > 0 {
+                eprintln!("Skipped {} directories in {} on other filesystems", listing.other_filesystems, root_path.display());
+            }
 
-    // Create a path using the HOME variable
-    let mut config_path = PathBuf::from(home);
-    config_path.push(".config/ggs/config.txt");
+            // `ggs ~/code/project` is about the project itself, as much as
+            // about any repository inside it.
+            let mut candidates = listing.directories;
+            if root_path.join(".git").exists() {
+                candidates.insert(0, root_path.clone());
+            }
+
+            // A directory under several roots is shown under the shortest of
+            // them, the first one given when they are as short.
+            let depth = root_path.components().count();
+            for directory in candidates {
+                let name = match display_name(&directory, &root_path, root.label.as_deref()) {
+                    Some(name) => name,
+                    None => continue,
+                };
+                match seen.get_mut(&directory) {
+                    Some((index, Some(found_depth))) if depth < *found_depth => {
+                        debug!("{}: found again under {}, a shorter root", directory.display(), root_path.display());
+                        directories[*index].1 = name;
+                        directories[*index].2 = root.label.clone();
+                        *found_depth = depth;
+                    }
+                    Some(_) => {}
+                    None => {
+                        seen.insert(directory.clone(), (directories.len(), Some(depth)));
+                        directories.push((directory, name, root.label.clone()));
+                    }
+                }
+            }
+            root_paths.push(root_path);
+        }
+    }
+
+    for repository in &targets.repositories {
+        let directory = match resolve_repository(repository) {
+            Ok(directory) => directory,
+            Err(error) => {
+                unreadable.push((repository.clone(), error));
+                continue;
+            }
+        };
+        // Not a repository itself, so a directory of repositories.
+        let candidates = if directory.join(".git").exists() {
+            vec![directory]
+        } else {
+            match list_directories(&directory, options, &excludes) {
+                Ok(listing) => listing.directories,
+                Err(error) => {
+                    unreadable.push((directory.display().to_string(), error));
+                    continue;
+                }
+            }
+        };
+        for directory in candidates {
+            if seen.contains_key(&directory) {
+                continue;
+            }
+            if let Some(name) = directory.to_str().map(String::from) {
+                seen.insert(directory.clone(), (directories.len(), None));
+                directories.push((directory, name, None));
+            }
+        }
+    }
+
+    let mut unreachable: Vec<(String, AppError)> = Vec::new();
+    let mut listed: HashSet<PathBuf> = HashSet::new();
+    for repository in &targets.listed {
+        let directory = match resolve_repository(repository) {
+            Ok(directory) => directory,
+            Err(error) => {
+                unreachable.push((repository.clone(), error));
+                continue;
+            }
+        };
+        listed.insert(directory.clone());
+        if seen.contains_key(&directory) {
+            continue;
+        }
+        if let Some(name) = directory.to_str().map(String::from) {
+            seen.insert(directory.clone(), (directories.len(), None));
+            directories.push((directory, name, None));
+        }
+    }
+
+    // A repository reachable through several paths is only checked under one
+    // of them: the one found under the shortest root, the first one found
+    // when they are as short.
+    let depth = |directory: &PathBuf| seen.get(directory).and_then(|&(_, depth)| depth).unwrap_or(usize::MAX);
+    let ids: Vec<Option<RepositoryId>> = directories.iter().map(|(directory, ..)| repository_id(directory)).collect();
+    let mut kept: HashMap<&RepositoryId, usize> = HashMap::new();
+    for (index, id) in ids.iter().enumerate() {
+        if let Some(id) = id {
+            let first = *kept.entry(id).or_insert(index);
+            if depth(&directories[index].0) < depth(&directories[first].0) {
+                kept.insert(id, index);
+            }
+        }
+    }
+    let duplicate_of: Vec<Option<PathBuf>> = ids.iter()
+        .enumerate()
+        .map(|(index, id)| match id.as_ref().map(|id| kept[id]) {
+            Some(kept) if kept != index => Some(directories[kept].0.clone()),
+            _ => None,
+        })
+        .collect();
+    // `retain` visits the directories in order.
+    let mut duplicate_of = duplicate_of.into_iter();
+    directories.retain(|(directory, ..)| match duplicate_of.next().flatten() {
+        Some(kept) => {
+            if options.verbose {
+                eprintln!("Skipped {}, the same repository as {}", directory.display(), kept.display());
+            }
+            debug!("{}: skipped, the same repository as {}", directory.display(), kept.display());
+            false
+        }
+        None => true,
+    });
+
+    if options.short || config.short_paths {
+        shorten_names(&mut directories);
+    }
+
+    // A submodule is part of its superproject's status already.
+    if !options.no_submodule_dedup {
+        let found: HashSet<PathBuf> = directories.iter().map(|(directory, ..)| directory.clone()).collect();
+        directories.retain(|(directory, ..)| match superproject(directory, &found) {
+            Some(parent) => {
+                if options.verbose {
+                    eprintln!("Skipped {}, a submodule of {}", directory.display(), parent.display());
+                }
+                debug!("{}: skipped, a submodule of {}", directory.display(), parent.display());
+                false
+            }
+            None => true,
+        });
+    }
+
+    // Repositories can opt out of being checked with a marker file.
+    let mut skipped: Vec<PathBuf> = Vec::new();
+    if !options.no_skip_markers {
+        directories.retain(|(directory, ..)| {
+            let skip = directory.join(SKIP_MARKER).exists();
+            if skip {
+                debug!("{}: skipped, contains {}", directory.display(), SKIP_MARKER);
+                skipped.push(directory.clone());
+            }
+            !skip
+        });
+    }
+
+    // And be left out centrally, in the config.
+    let skip_rules = config.skip_patterns();
+    let mut skipped_by_config: Vec<PathBuf> = Vec::new();
+    directories.retain(|(directory, ..)| {
+        let skip = skip_rules.iter().find(|(_, pattern)| pattern.matches_path(directory));
+        if let Some((entry, _)) = skip {
+            debug!("{}: skipped by config entry '{}'", directory.display(), entry);
+            skipped_by_config.push(directory.clone());
+        }
+        let skip = skip.is_some();
+        !skip
+    });
+    // Only repositories on a matching branch are checked. A detached or unborn
+    // HEAD matches no pattern; directories that aren't repositories are left
+    // for the scan to deal with.
+    let mut filtered_by_branch: Vec<PathBuf> = Vec::new();
+    if options.on_branch.is_some() || options.not_on_branch.is_some() {
+        directories.retain(|(directory, ..)| {
+            let repo = match open_repository(directory) {
+                Ok(repo) => repo,
+                Err(_) => return true,
+            };
+            let branch = current_branch(&repo);
+            let matches = |pattern: &Pattern| branch.as_deref().is_some_and(|branch| pattern.matches(branch));
+            let keep = options.on_branch.as_ref().is_none_or(matches) && !options.not_on_branch.as_ref().is_some_and(matches);
+            if !keep {
+                debug!("{}: filtered out, on branch {}", directory.display(), branch.as_deref().unwrap_or("(none)"));
+                filtered_by_branch.push(directory.clone());
+            }
+            keep
+        });
+    }
+
+    // Only repositories with a remote on a matching host. Those without any
+    // remote are counted apart, as no pattern could have matched them.
+    let mut filtered_by_host: Vec<PathBuf> = Vec::new();
+    let mut without_remotes = 0;
+    if let Some(pattern) = &options.host {
+        directories.retain(|(directory, ..)| {
+            let repo = match open_repository(directory) {
+                Ok(repo) => repo,
+                Err(_) => return true,
+            };
+            let urls: Vec<String> = if options.any_remote {
+                let names = repo.remotes().map(|names| names.iter().flatten().map(String::from).collect()).unwrap_or_else(|_| Vec::new());
+                names.iter().filter_map(|name| repo.find_remote(name).ok()?.url().map(String::from)).collect()
+            } else {
+                origin_url(&repo).into_iter().collect()
+            };
+            let keep = urls.iter().filter_map(|url| remote_host(url)).any(|host| pattern.matches(&host));
+            if !keep {
+                let hosts: Vec<String> = urls.iter().filter_map(|url| remote_host(url)).collect();
+                debug!("{}: filtered out, remote hosts: {}", directory.display(), if hosts.is_empty() { String::from("(none)") } else { hosts.join(", ") });
+                if repo.remotes().is_ok_and(|names| names.is_empty()) {
+                    without_remotes += 1;
+                }
+                filtered_by_host.push(directory.clone());
+            }
+            keep
+        });
+    }
+
+    if options.verbose {
+        for (entry, pattern) in &skip_rules {
+            let exists = glob::glob(pattern.as_str()).is_ok_and(|mut paths| paths.any(|path| path.is_ok()));
+            if !exists {
+                eprintln!("Warning: skip entry '{}' in the config doesn't match any directory", entry);
+            }
+        }
+    }
+
+    Found {
+        directories,
+        root_paths,
+        unreadable,
+        unreachable,
+        listed,
+        skipped,
+        skipped_by_config,
+        filtered_by_branch,
+        filtered_by_host,
+        without_remotes,
+    }
+}
+
+/// Whether a problem `--fail-on` asks about kept some repositories from being checked.
+fn is_partial(found: &Found, checked: &[Checked], options: &Options) -> bool {
+    let fails = |problem: Problem| options.fail_on.contains(&problem);
+    if fails(Problem::Unreachable) && !(found.unreadable.is_empty() && found.unreachable.is_empty()) {
+        return true;
+    }
+    checked.iter().any(|checked| match checked.outcome() {
+        Outcome::Locked(_) => fails(Problem::Locked),
+        Outcome::Corrupt(..) | Outcome::Failed(_) => fails(Problem::Corrupt),
+        Outcome::TimedOut => fails(Problem::TimedOut),
+        Outcome::NotOwned => fails(Problem::NotOwned),
+        Outcome::NotARepository if found.listed.contains(&checked.directory) => fails(Problem::Unreachable),
+        // Asked for with `--only-repos`, whatever `--fail-on` says.
+        Outcome::NotARepository => options.only_repos,
+        Outcome::Checked(..) | Outcome::BareSkipped(_) => false,
+    })
+}
+
+/// The `--json` document for a finished scan.
+fn render_json(found: &Found, checked: &[Checked], summary: Summary, timestamp: i64, duration: Duration) -> String {
+    let mut errors: Vec<ErrorEntry> = found.unreadable.iter()
+        .chain(&found.unreachable)
+        .map(|(path, error)| ErrorEntry::new(path, error))
+        .collect();
+    for checked in checked {
+        let message = match checked.outcome() {
+            Outcome::Failed(message) | Outcome::Corrupt(_, message) => message,
+            Outcome::Locked(_) => String::from("the index is locked"),
+            _ => continue,
+        };
+        errors.push(ErrorEntry::new(checked.directory.display(), message));
+    }
+
+    let document = JsonReport {
+        roots: found.root_paths.iter().map(|root| root.display().to_string()).collect(),
+        timestamp,
+        duration_ms: duration.as_millis() as u64,
+        repositories: checked.iter()
+            .filter_map(Checked::record)
+            .filter_map(|record| serde_json::to_value(record).ok())
+            .collect(),
+        summary,
+        errors,
+    };
+    match serde_json::to_string_pretty(&document) {
+        Ok(json) => format!("{}\n", json),
+        Err(_) => String::new(),
+    }
+}
+
+/// The text report for a finished scan: a section per status, or a list of
+/// repositories with `--group-by`, then everything else worth knowing and
+/// the summary line.
+fn render_report(
+    found: &Found,
+    checked: &[Checked],
+    expected_branches: &HashMap<PathBuf, String>,
+    summary: &Summary,
+    stopped_early: bool,
+    options: &Options,
+    now: i64,
+) -> String {
+    let mut in_progress: Vec<String> = Vec::new();
+    let mut modified: Vec<String> = Vec::new();
+    let mut staged: Vec<String> = Vec::new();
+    let mut unpushed_commits: Vec<String> = Vec::new();
+    let mut upstream_gone: Vec<String> = Vec::new();
+    let mut no_upstream: Vec<String> = Vec::new();
+    let mut stale: Vec<(Option<i64>, String)> = Vec::new();
+    let mut empty: Vec<String> = Vec::new();
+    let mut bare: Vec<String> = Vec::new();
+    // What was found in each repository, for `--group-by repo`.
+    let mut by_repo: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    // The same, by the owner of their `origin`, for `--group-by org`.
+    let mut by_org: BTreeMap<String, Vec<String>> = BTreeMap::new();
+
+    let mut bare_skipped: Vec<String> = Vec::new();
+    let mut unexpected_branch: Vec<String> = Vec::new();
+    let mut ahead_of_base: Vec<String> = Vec::new();
+    let mut default_branch_drift: Vec<String> = Vec::new();
+    // Grouped by the identity that was found.
+    let mut unexpected_identity: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    let mut needs_gc: Vec<String> = Vec::new();
+    let mut gc_object_dirs: HashSet<&Path> = HashSet::new();
+    let mut not_fetched: Vec<(Option<i64>, String)> = Vec::new();
+    let mut locked: Vec<String> = Vec::new();
+    let mut corrupt: Vec<String> = Vec::new();
+    let mut errors: Vec<String> = Vec::new();
+    let mut timed_out: Vec<String> = Vec::new();
+    let mut not_owned: Vec<String> = Vec::new();
+    let mut invalid: Vec<String> = Vec::new();
+    // Scanned directories that aren't repositories, with `--only-repos`.
+    let mut not_repositories: Vec<String> = Vec::new();
+    let mut repositories: HashSet<&Path> = HashSet::new();
+    let width = terminal_width();
+
+    for checked in checked {
+        let path = checked.name.clone();
+        let (result, check) = match checked.outcome() {
+            Outcome::Checked(result, check) => (result, Some(check)),
+            Outcome::Locked(result) => {
+                locked.push(path.clone());
+                (result, None)
+            }
+            Outcome::Corrupt(result, detail) => {
+                corrupt.push(format!("{} ({})", path, detail));
+                (result, None)
+            }
+            Outcome::Failed(message) => {
+                repositories.insert(&checked.directory);
+                errors.push(format!("{} ({})", path, message));
+                continue
+            }
+            Outcome::TimedOut => {
+                repositories.insert(&checked.directory);
+                timed_out.push(path);
+                continue
+            }
+            Outcome::NotOwned => {
+                not_owned.push(path);
+                continue
+            }
+            // Only checked for `--maintenance`.
+            Outcome::BareSkipped(result) => {
+                bare_skipped.push(path.clone());
+                (result, None)
+            }
+            // Most directories in a scanned root are expected not to be
+            // repositories, unless `--only-repos` says otherwise.
+            Outcome::NotARepository => {
+                if found.listed.contains(&checked.directory) {
+                    invalid.push(path);
+                } else if options.only_repos {
+                    not_repositories.push(path);
+                }
+                continue
+            }
+        };
+        repositories.insert(&checked.directory);
+
+        if let Some((expected, actual)) = &result.unexpected_branch {
+            unexpected_branch.push(format!("{} (expected {}, on {})", path, expected, actual));
+        }
+
+        if let Some(ahead) = result.ahead_of_base {
+            let noun = if ahead == 1 { "commit" } else { "commits" };
+            ahead_of_base.push(format!("{} ({} {})", path, ahead, noun));
+        }
+
+        if let Some(drift) = &result.default_branch_drift {
+            default_branch_drift.push(format!("{} ({} {} ahead, {} behind {}/{})", path, drift.name, drift.ahead, drift.behind, options.remote, drift.name));
+        }
+
+        if let Some(identity) = &result.unexpected_identity {
+            let source = if identity.local { "repository config" } else { "global config" };
+            let group = format!(
+                "{} <{}> ({})",
+                identity.name.as_deref().unwrap_or("no name"),
+                identity.email.as_deref().unwrap_or("no email"),
+                source,
+            );
+            unexpected_identity.entry(group).or_default().push(path.clone());
+        }
+
+        // Worktrees share their object directory with the main repository,
+        // so only report it once.
+        if let Some((objects_dir, reason)) = &result.needs_gc {
+            if gc_object_dirs.insert(objects_dir) {
+                needs_gc.push(format!("{} ({})", path, reason));
+            }
+        }
+
+        let check = match check {
+            Some(check) => check,
+            None => continue,
+        };
+
+        if let Some(days) = options.not_fetched_since {
+            let age = result.last_fetch.map(|time| now - time);
+            if result.has_remotes && age.is_none_or(|age| age > days as i64 * SECONDS_PER_DAY) {
+                not_fetched.push((result.last_fetch, path.clone()));
+            }
+        }
+
+        let entry = format!("{}{}", path, result.annotation);
+        let branches_ahead: Vec<&str> = check.branches.iter()
+            .filter(|branch| branch.ahead > 0)
+            .map(|branch| branch.name.as_str())
+            .collect();
+        let mut dirty_entry = match check.dirty_since {
+            Some(since) => format!("{} (dirty for {})", entry, humanize_duration(now - since)),
+            None => entry.clone(),
+        };
+        if options.verbose {
+            match &check.diff_stat {
+                Some(DiffStat::Lines { insertions, deletions, files }) => {
+                    let noun = if *files == 1 { "file" } else { "files" };
+                    dirty_entry.push_str(&format!(" (+{} -{} lines across {} {})", insertions, deletions, files, noun));
+                }
+                Some(DiffStat::TooLarge) => dirty_entry.push_str(" (diff too large)"),
+                None => {}
+            }
+        }
+        let mut entry = entry;
+        if options.show_last_commit {
+            entry = with_last_commit(&entry, check.last_commit.as_ref(), now, width);
+            dirty_entry = with_last_commit(&dirty_entry, check.last_commit.as_ref(), now, width);
+        }
+        let mut unpushed_entry = if options.verbose && !check.branches.is_empty() {
+            let branches: Vec<String> = check.branches.iter()
+                .map(|branch| format!("{} {} ahead/{} behind", branch.name, branch.ahead, branch.behind))
+                .collect();
+            format!("{} (branches: {})", entry, branches.join(", "))
+        } else if !branches_ahead.is_empty() {
+            format!("{} (branches ahead: {})", entry, branches_ahead.join(", "))
+        } else {
+            entry.clone()
+        };
+        if options.verbose {
+            let remote = format!("\n    remote: {}", check.remote_url.as_deref().unwrap_or("(no remote)"));
+            for entry in [&mut entry, &mut dirty_entry, &mut unpushed_entry] {
+                entry.push_str(&remote);
+            }
+        }
+
+        if options.group_by != GroupBy::Status {
+            let dirty_for = check.dirty_since.map(|since| format!(" (dirty for {})", humanize_duration(now - since))).unwrap_or_default();
+            let findings: Vec<String> = check.statuses.iter()
+                .map(|status| match status {
+                    GitStatus::InProgress(operation) => format!("{} in progress", operation),
+                    GitStatus::Modified => format!("modified files{}", dirty_for),
+                    GitStatus::Staged => format!("staged changes{}", dirty_for),
+                    GitStatus::UnpushedCommits if !branches_ahead.is_empty() => format!("unpushed commits on {}", branches_ahead.join(", ")),
+                    GitStatus::UnpushedCommits => String::from("unpushed commits"),
+                    GitStatus::UpstreamGone => String::from("upstream branch gone"),
+                    GitStatus::NoUpstream => String::from("no remote tracking branch"),
+                    GitStatus::Stale => String::from("no recent commits"),
+                    GitStatus::Empty => String::from("no commits"),
+                    GitStatus::BareRepo => String::from("bare repository"),
+                })
+                .collect();
+            if findings.is_empty() {
+                // Nothing to list it under.
+            } else if options.group_by == GroupBy::Org {
+                let owner = check.remote_url.as_deref().and_then(|url| Some(format!("{}/{}", remote_host(url)?, remote_owner(url)?)));
+                let owner = owner.unwrap_or_else(|| String::from(NO_REMOTE_GROUP));
+                by_org.entry(owner).or_default().push(format!("{} ({})", path, findings.join(", ")));
+            } else {
+                by_repo.insert(path.clone(), findings);
+            }
+        }
+        for status in &check.statuses {
+            match status {
+                GitStatus::InProgress(operation) => in_progress.push(format!("{} ({})", entry, operation)),
+                GitStatus::Modified => modified.push(dirty_entry.clone()),
+                GitStatus::Staged => staged.push(dirty_entry.clone()),
+                GitStatus::UnpushedCommits => unpushed_commits.push(unpushed_entry.clone()),
+                GitStatus::UpstreamGone => upstream_gone.push(entry.clone()),
+                GitStatus::NoUpstream => no_upstream.push(entry.clone()),
+                GitStatus::Stale => stale.push((check.last_commit.as_ref().map(|commit| commit.time), path.clone())),
+                GitStatus::Empty => empty.push(entry.clone()),
+                GitStatus::BareRepo => bare.push(entry.clone()),
+            }
+        }
+    }
+
+    // Configured repositories under this root that the scan never came across
+    // have most likely been moved or deleted.
+    let mut missing: Vec<String> = expected_branches.keys()
+        .filter(|_| !stopped_early)
+        .filter(|configured| found.root_paths.iter().any(|root| configured.starts_with(root)))
+        .filter(|configured| !repositories.contains(configured.as_path()))
+        .filter(|configured| !found.skipped.contains(configured) && !found.skipped_by_config.contains(configured) && !found.filtered_by_branch.contains(configured) && !found.filtered_by_host.contains(configured))
+        .map(|configured| configured.display().to_string())
+        .collect();
+    missing.sort();
+
+    // Oldest first, repositories without any commit before everything else.
+    stale.sort();
+    let stale: Vec<String> = stale.into_iter()
+        .map(|(time, path)| match time {
+            Some(time) => format!("{} ({})", path, humanize_duration(now - time)),
+            None => format!("{} (no commits)", path),
+        })
+        .collect();
+    let stale_msg = format!("Possibly stale repositories (no commit in {} days):", options.check.stale_days.unwrap_or(0));
+
+    // Never fetched first, then the longest ago.
+    not_fetched.sort();
+    let not_fetched: Vec<String> = not_fetched.into_iter()
+        .map(|(time, path)| match time {
+            Some(time) => format!("{} (fetched {} ago)", path, humanize_duration(now - time)),
+            None => format!("{} (never fetched)", path),
+        })
+        .collect();
+
+    let unreadable: Vec<String> = found.unreadable.iter().map(|(_, error)| error.to_string()).collect();
+    let unreachable: Vec<String> = found.unreachable.iter().map(|(_, error)| error.to_string()).collect();
+    let base_msg = format!("Repositories ahead of base ref {} (not yet released):", options.base.as_deref().unwrap_or_default());
+
+    // Replaced by the list of repositories with `--group-by repo`.
+    let status_sections = [
+        (&in_progress, IN_PROGRESS_MSG),
+        (&unpushed_commits, UNPUSHED_COMMITS_MSG),
+        (&staged, STAGED_CHANGES_MSG),
+        (&modified, MODIFIED_FILES_MSG),
+        (&upstream_gone, UPSTREAM_GONE_MSG),
+        (&no_upstream, NO_UPSTREAM_MSG),
+        (&stale, stale_msg.as_str()),
+        (&empty, EMPTY_MSG),
+        (&bare, BARE_MSG),
+    ];
+    let sections = [
+        (&ahead_of_base, base_msg.as_str()),
+        (&default_branch_drift, DEFAULT_BRANCH_DRIFT_MSG),
+        (&unexpected_branch, UNEXPECTED_BRANCH_MSG),
+        (&missing, MISSING_CONFIGURED_MSG),
+        (&not_fetched, NOT_FETCHED_MSG),
+        (&needs_gc, NEEDS_GC_MSG),
+        (&locked, LOCKED_MSG),
+        (&corrupt, CORRUPT_MSG),
+        (&errors, ERRORS_MSG),
+        (&timed_out, TIMED_OUT_MSG),
+        (&not_owned, NOT_OWNED_MSG),
+        (&unreadable, UNREADABLE_MSG),
+        (&unreachable, UNREACHABLE_MSG),
+        (&invalid, INVALID_ENTRIES_MSG),
+        (&not_repositories, NOT_A_REPOSITORY_MSG),
+    ];
+
+    let mut report = String::new();
+    if status_sections.iter().chain(&sections).all(|(directories, _)| directories.is_empty()) && unexpected_identity.is_empty() {
+        report.push_str(&format!("{}\n", ALL_GOOD));
+    }
+
+    match options.group_by {
+        GroupBy::Status => {
+            for (directories, message) in status_sections {
+                print_status(&mut report, directories, message, &options.layout);
+            }
+        }
+        GroupBy::Repo => print_groups(&mut report, &by_repo, &options.layout),
+        GroupBy::Org => {
+            // Repositories without an owner come last.
+            let owned = by_org.iter().filter(|(owner, _)| *owner != NO_REMOTE_GROUP);
+            print_groups(&mut report, owned.chain(by_org.get_key_value(NO_REMOTE_GROUP)), &options.layout);
+        }
+    }
+    for (directories, message) in sections {
+        print_status(&mut report, directories, message, &options.layout);
+    }
+
+    if !unexpected_identity.is_empty() {
+        let layout = &options.layout;
+        if layout.headings {
+            report.push_str(&format!("{}\n", UNEXPECTED_IDENTITY_MSG));
+        }
+        for (identity, directories) in &unexpected_identity {
+            if layout.headings {
+                report.push_str(&format!("  {}\n", identity));
+            }
+            // Nested under the identity, unless only the entries are printed.
+            let indent = if layout.headings { "  " } else { "" };
+            for directory in directories {
+                report.push_str(&format!("{}{}{}\n", indent, layout.bullet, directory));
+            }
+        }
+    }
+
+    // Not a problem, just explains why they aren't in the report.
+    if options.verbose {
+        print_status(&mut report, &bare_skipped, BARE_SKIPPED_MSG, &options.layout);
+    }
+
+    if !found.skipped.is_empty() {
+        let noun = if found.skipped.len() == 1 { "repository" } else { "repositories" };
+        report.push_str(&format!("{} {} skipped ({})\n", found.skipped.len(), noun, SKIP_MARKER));
+    }
+    if !found.skipped_by_config.is_empty() {
+        let noun = if found.skipped_by_config.len() == 1 { "repository" } else { "repositories" };
+        report.push_str(&format!("{} {} skipped by config\n", found.skipped_by_config.len(), noun));
+    }
+    if options.on_branch.is_some() || options.not_on_branch.is_some() {
+        let noun = if found.filtered_by_branch.len() == 1 { "repository" } else { "repositories" };
+        report.push_str(&format!("{} {} filtered out by branch\n", found.filtered_by_branch.len(), noun));
+    }
+    if options.host.is_some() {
+        let noun = if found.filtered_by_host.len() == 1 { "repository" } else { "repositories" };
+        report.push_str(&format!("{} {} filtered out by host ({} without remotes)\n", found.filtered_by_host.len(), noun, found.without_remotes));
+    }
+
+    // How many repositories are in each state, even when they are all clean.
+    if options.quiet {
+        report.clear();
+    }
+    report.push_str(&format!("{}\n", summary.line()));
+
+    report
+}
+
+/// Shows repositories by their directory name rather than their full path,
+/// unless several share the same name. Names from labelled roots are kept.
+fn shorten_names(directories: &mut [(PathBuf, String, Option<String>)]) {
+    let basename = |directory: &Path| directory.file_name().and_then(OsStr::to_str).map(String::from);
+
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for (directory, ..) in directories.iter() {
+        if let Some(name) = basename(directory) {
+            *counts.entry(name).or_default() += 1;
+        }
+    }
+
+    for (directory, name, _) in directories.iter_mut() {
+        if directory.to_str() != Some(name.as_str()) {
+            continue;
+        }
+        if let Some(short) = basename(directory).filter(|short| counts.get(short) == Some(&1)) {
+            *name = short;
+        }
+    }
+}
+
+/// Tells repositories apart by their git directory. Its device and inode on
+/// unix, so bind mounts are recognised as well as symlinks.
+#[cfg(unix)]
+type RepositoryId = (u64, u64);
+#[cfg(not(unix))]
+type RepositoryId = PathBuf;
+
+/// The id of the git directory of the repository at `directory`, `None` when
+/// it doesn't look like a repository. Worktrees and submodules have a `.git`
+/// file pointing to a git directory of their own.
+fn repository_id(directory: &Path) -> Option<RepositoryId> {
+    let dot_git = directory.join(".git");
+    let git_dir = if dot_git.is_file() {
+        let contents = std::fs::read_to_string(&dot_git).ok()?;
+        directory.join(contents.trim().strip_prefix("gitdir:")?.trim())
+    } else if dot_git.is_dir() {
+        dot_git
+    } else if directory.join("HEAD").is_file() {
+        // Bare repositories are their own git directory.
+        directory.to_path_buf()
+    } else {
+        return None;
+    };
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        let metadata = std::fs::metadata(git_dir).ok()?;
+        Some((metadata.dev(), metadata.ino()))
+    }
+    #[cfg(not(unix))]
+    {
+        std::fs::canonicalize(git_dir).ok()
+    }
+}
+
+/// The repository among `found` that `directory` is a submodule of, if any.
+/// Submodules have a `.git` file rather than a directory, so others are not
+/// looked into any further.
+fn superproject<'a>(directory: &Path, found: &'a HashSet<PathBuf>) -> Option<&'a PathBuf> {
+    if !directory.join(".git").is_file() {
+        return None;
+    }
+
+    directory.ancestors().skip(1)
+        .filter_map(|ancestor| found.get(ancestor))
+        .find(|parent| {
+            let relative = match directory.strip_prefix(parent) {
+                Ok(relative) => relative,
+                Err(_) => return false,
+            };
+            open_repository(parent)
+                .is_ok_and(|repo| repo.find_submodule(&relative.to_string_lossy()).is_ok())
+        })
+}
+
+/// The directories a root given by the user stands for: every directory
+/// matching it when it is a glob pattern, otherwise the root itself.
+fn resolve_roots(path: &str) -> Result<Vec<PathBuf>, AppError> {
+    if !path.contains(['*', '?', '[']) {
+        return Ok(vec![resolve_root(path)?]);
+    }
+
+    let expanded = expand_path(path)?;
+    let pattern = match expanded.to_str() {
+        Some(pattern) => pattern,
+        None => return Err(AppError::NonUnicodePath(expanded)),
+    };
+    let matches = match glob::glob(pattern) {
+        Ok(matches) => matches,
+        Err(error) => return Err(AppError::InvalidPattern { pattern: String::from(path), message: error.to_string() }),
+    };
+
+    // Files matching the pattern are not roots.
+    let mut roots: Vec<PathBuf> = matches.flatten()
+        .filter(|path| path.is_dir())
+        .map(|path| std::fs::canonicalize(&path).unwrap_or(path))
+        .collect();
+    roots.sort();
+    roots.dedup();
+
+    if roots.is_empty() {
+        return Err(AppError::NoMatches(String::from(path)));
+    }
+    Ok(roots)
+}
+
+/// Expands and canonicalizes a root given by the user, making sure it is a directory.
+fn resolve_root(path: &str) -> Result<PathBuf, AppError> {
+    let path = expand_path(path)?;
+    let path = std::fs::canonicalize(&path).map_err(|e| AppError::directory(path, e))?;
+    if !path.is_dir() {
+        return Err(AppError::NotADirectory(path));
+    }
+
+    Ok(path)
+}
+
+/// Expands and canonicalizes a repository given by the user. The `.git`
+/// directory of a repository stands for the repository itself.
+fn resolve_repository(path: &str) -> Result<PathBuf, AppError> {
+    let path = expand_path(path)?;
+    let path = std::fs::canonicalize(&path).map_err(|e| AppError::directory(path, e))?;
+    if !path.is_dir() {
+        return Err(AppError::NotADirectory(path));
+    }
+    match path.parent() {
+        Some(parent) if path.file_name() == Some(OsStr::new(".git")) => Ok(parent.to_path_buf()),
+        _ => Ok(path),
+    }
+}
+
+/// How `directory` is shown in the report: its path, or relative to the
+/// root's label when the root has one, the label alone for the root itself. `None` for paths that aren't UTF-8.
+fn display_name(directory: &Path, root: &Path, label: Option<&str>) -> Option<String> {
+    let path = directory.to_str()?;
+    let label = match label {
+        Some(label) => label,
+        None => return Some(String::from(path)),
+    };
 
-    let contents = std::fs::read_to_string(config_path)?;
+    match directory.strip_prefix(root) {
+        Ok(relative) if relative.as_os_str().is_empty() => Some(String::from(label)),
+        Ok(relative) => Some(format!("{}/{}", label, relative.to_str()?)),
+        Err(_) => Some(String::from(path)),
+    }
+}
+
+/// Lists the subdirectories of `path`. Directories starting with a `.` are
+/// skipped unless `--hidden` or the config asks for them, symlinks unless
+/// `--follow-symlinks` is given, and those on network filesystems unless
+/// `--include-network` is or the root is on one itself;
+/// `.git` itself is never returned.
+fn list_directories(path: &Path, options: &Options, excludes: &[Pattern]) -> Result<Listing, AppError> {
+    trace!("{}: listing", path.display());
+    let entries = match path.read_dir() {
+        Ok(entries) => entries,
+        Err(error) => return Err(AppError::io(path, error)),
+    };
+
+    let ignores = load_local_ignores(path);
+    let mounts = Mounts::read();
+    let skip_network = !options.include_network && !mounts.is_network(path);
+    let device = device_id(path);
+
+    let mut directories: Vec<PathBuf> = Vec::new();
+    let mut excluded = 0;
+    let mut symlinks = 0;
+    let mut network = 0;
+    let mut other_filesystems = 0;
+    for dir in entries.flatten() {
+        trace!("{}: listed", dir.path().display());
+        let name = dir.file_name();
+        if name == ".git" {
+            continue;
+        }
+        if !options.hidden && name.to_string_lossy().starts_with('.') {
+            debug!("{}: skipped, hidden", dir.path().display());
+            continue;
+        }
+        if dir.file_type().is_ok_and(|file_type| file_type.is_symlink()) {
+            if !options.follow_symlinks {
+                debug!("{}: skipped, a symlink", dir.path().display());
+                symlinks += 1;
+                continue;
+            }
+            // A link back up the tree would have the directory scan itself.
+            if std::fs::canonicalize(dir.path()).is_ok_and(|target| path.starts_with(target)) {
+                debug!("{}: skipped, links to a parent directory", dir.path().display());
+                continue;
+            }
+        }
+        if  dir.path().is_dir() {
+            if is_excluded(&dir.path(), path, excludes) || is_ignored(&dir.path(), path, &ignores) {
+                debug!("{}: skipped, excluded", dir.path().display());
+                excluded += 1;
+                continue;
+            }
+            // Resolve symlinks so a directory reachable by several names is only listed once.
+            let directory = std::fs::canonicalize(dir.path()).unwrap_or(dir.path());
+            // Every stat on a network mount may take a round trip.
+            if skip_network && mounts.is_network(&directory) {
+                debug!("{}: skipped, on a network filesystem", directory.display());
+                network += 1;
+                continue;
+            }
+            if options.one_file_system && device.is_some() && device_id(&directory) != device {
+                debug!("{}: skipped, on another filesystem", directory.display());
+                other_filesystems += 1;
+                continue;
+            }
+            debug!("{}: found", directory.display());
+            directories.push(directory);
+        }
+    }
+    directories.sort();
+    directories.dedup();
     
-    Ok(contents)
+    Ok(Listing { directories, excluded, symlinks, network, other_filesystems })
+}
+
+/// The device `path` is on, to tell filesystems apart. `None` where that
+/// can't be told, in which case every directory counts as on the same one.
+fn device_id(path: &Path) -> Option<u64> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        std::fs::metadata(path).ok().map(|metadata| metadata.dev())
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = path;
+        None
+    }
+}
+
+/// Rules from the `.ggsignore` file in `root`, one glob per line, relative to
+/// `root`. Blank lines and `#` comments are skipped, and a rule starting with
+/// `!` brings back directories an earlier rule ignored.
+fn load_local_ignores(root: &Path) -> Vec<IgnoreRule> {
+    let path = root.join(IGNORE_FILE);
+    let contents = match std::fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(error) if error.kind() == ErrorKind::NotFound => return Vec::new(),
+        Err(error) => {
+            eprintln!("Warning: {}. Ignoring it.", AppError::io(path, error));
+            return Vec::new();
+        }
+    };
+
+    let mut rules = Vec::new();
+    for (number, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (negated, glob) = match line.strip_prefix('!') {
+            Some(glob) => (true, glob),
+            None => (false, line),
+        };
+        match Glob::new(glob.trim_end_matches('/')) {
+            Ok(glob) => rules.push(IgnoreRule { matcher: glob.compile_matcher(), negated }),
+            Err(error) => eprintln!("Warning: {}:{}: invalid pattern '{}': {}. Ignoring it.", path.display(), number + 1, line, error),
+        }
+    }
+
+    rules
+}
+
+/// Whether the last `.ggsignore` rule matching `directory` ignores it.
+fn is_ignored(directory: &Path, root: &Path, rules: &[IgnoreRule]) -> bool {
+    rules.iter().rev()
+        .find(|rule| {
+            let name = directory.file_name().unwrap_or_default();
+            let relative = directory.strip_prefix(root).unwrap_or(directory);
+            rule.matcher.is_match(name) || rule.matcher.is_match(relative)
+        })
+        .is_some_and(|rule| !rule.negated)
+}
+
+/// Whether an exclude pattern matches the name of `directory` or its path
+/// relative to `root`.
+fn is_excluded(directory: &Path, root: &Path, excludes: &[Pattern]) -> bool {
+    excludes.iter().any(|pattern| matches_directory(pattern, directory, root))
+}
+
+fn matches_directory(pattern: &Pattern, directory: &Path, root: &Path) -> bool {
+    let name = directory.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+    let relative = directory.strip_prefix(root).unwrap_or(directory);
+    pattern.matches(&name) || pattern.matches_path(relative)
+}
+    
+/// A repository to check, with what is expected of it.
+struct Job {
+    directory: PathBuf,
+    /// How the repository is shown in the report.
+    name: String,
+    /// The label of the root it was found under.
+    root_label: Option<String>,
+    expectations: Expectations,
+}
+
+/// Checks every repository on a pool of `threads` threads, or one per CPU for
+/// 0, in the order of `jobs`. `None` for those skipped by `--fail-fast`.
+#[cfg(not(feature = "tokio"))]
+fn check_repositories(jobs: Vec<Job>, options: &Options, threads: usize) -> Vec<Option<Checked>> {
+    let cancelled = AtomicBool::new(false);
+    build_thread_pool(threads).install(|| {
+        jobs.into_par_iter()
+            .map(|job| check_repository(job, options, &cancelled))
+            .collect()
+    })
+}
+
+/// Checks every repository as a blocking task on a tokio runtime, at most
+/// `threads` at once, or as many as tokio allows for 0. Checks mostly wait on
+/// the disk, so many more of them than there are CPUs can run at once.
+#[cfg(feature = "tokio")]
+fn check_repositories(jobs: Vec<Job>, options: &Options, threads: usize) -> Vec<Option<Checked>> {
+    let mut builder = tokio::runtime::Builder::new_multi_thread();
+    if threads > 0 {
+        builder.max_blocking_threads(threads);
+    }
+    let runtime = match builder.build() {
+        Ok(runtime) => runtime,
+        Err(error) => {
+            eprintln!("Error: {}. Could not start scanning threads.", error);
+            exit(1);
+        }
+    };
+
+    let options = Arc::new(options.clone());
+    let cancelled = Arc::new(AtomicBool::new(false));
+    runtime.block_on(async {
+        let tasks: Vec<_> = jobs.into_iter()
+            .map(|job| {
+                let options = Arc::clone(&options);
+                let cancelled = Arc::clone(&cancelled);
+                tokio::task::spawn_blocking(move || check_repository(job, &options, &cancelled))
+            })
+            .collect();
+
+        let mut results = Vec::with_capacity(tasks.len());
+        for task in tasks {
+            // A task only fails when the check panicked.
+            results.push(task.await.unwrap_or_else(|error| std::panic::resume_unwind(error.into_panic())));
+        }
+        results
+    })
+}
+
+/// Checks a single repository, unless `cancelled` was set by `--fail-fast`
+/// finding a dirty one.
+fn check_repository(job: Job, options: &Options, cancelled: &AtomicBool) -> Option<Checked> {
+    if cancelled.load(Ordering::Relaxed) {
+        return None;
+    }
+    let work = {
+        let directory = job.directory.clone();
+        let options = options.clone();
+        let expectations = job.expectations;
+        move || scan_repository(&directory, &options, &expectations)
+    };
+    let result = run_with_timeout(options.timeout, work);
+    // With `-o`, records are written to the file once the scan is done instead.
+    if options.ndjson && options.output.is_none() {
+        if let Some(record) = RepoRecord::from_result(&job.directory, &job.name, job.root_label.as_deref(), &result) {
+            emit_record(&record);
+        }
+    }
+    if options.fail_fast && is_dirty(&result) {
+        cancelled.store(true, Ordering::Relaxed);
+    }
+    Some(Checked { directory: job.directory, name: job.name, root_label: job.root_label, result })
+}
+
+/// A pool of `threads` threads to check repositories with, or one per CPU
+/// for 0.
+#[cfg(not(feature = "tokio"))]
+fn build_thread_pool(threads: usize) -> rayon::ThreadPool {
+    match rayon::ThreadPoolBuilder::new().num_threads(threads).build() {
+        Ok(pool) => pool,
+        Err(error) => {
+            eprintln!("Error: {}. Could not start scanning threads.", error);
+            exit(1);
+        }
+    }
+}
+
+/// Prints `record` as one line of JSON. Holding the stdout lock for the whole
+/// line keeps lines from different threads apart.
+fn emit_record(record: &RepoRecord) {
+    let line = match serde_json::to_string(record) {
+        Ok(line) => line,
+        Err(_) => return,
+    };
+    // Flushed line by line, so consumers see every repository as soon as it
+    // was checked, even when stdout is a pipe.
+    let mut out = io::stdout().lock();
+    let _ = writeln!(out, "{}", line);
+    let _ = out.flush();
+}
+
+/// Whether a finished scan found the repository dirty.
+fn is_dirty(result: &Option<Result<RepoResult, AppError>>) -> bool {
+    matches!(result, Some(Ok(RepoResult { status: Ok(check), .. })) if check.statuses.iter().any(GitStatus::is_dirty))
+}
+
+/// Opens the repository at `directory` and runs every check on it.
+fn scan_repository(directory: &Path, options: &Options, expectations: &Expectations) -> Result<RepoResult, AppError> {
+    let repository = match open_repository(directory) {
+        Ok(repository) => repository,
+        Err(error) => {
+            debug!("{}: not opened: {}", directory.display(), error);
+            return Err(AppError::check(directory, error));
+        }
+    };
+    // Mirrors need `git gc` as much as any other repository, so this is
+    // checked before skipping them.
+    let mut needs_gc = None;
+    if let Some(thresholds) = &expectations.gc_thresholds {
+        let objects = objects_dir(&repository);
+        if let Some(reason) = gc_reason(&objects, thresholds) {
+            needs_gc = Some((objects, reason));
+        }
+    }
+
+    // Mirrors and other bare repositories have no working tree to check.
+    if repository.is_bare() && !options.include_bare {
+        debug!("{}: skipped, a bare repository", directory.display());
+        return Ok(RepoResult {
+            status: Err(AppError::BareRepository(directory.to_path_buf())),
+            annotation: String::new(),
+            unexpected_branch: None,
+            unexpected_identity: None,
+            needs_gc,
+            ahead_of_base: None,
+            default_branch_drift: None,
+            has_remotes: false,
+            last_fetch: None,
+        });
+    }
+
+    let mut unexpected_branch = None;
+    if let Some(expected) = expectations.branch.as_deref() {
+        let actual = current_branch(&repository);
+        if actual.as_deref() != Some(expected) {
+            let actual = actual.unwrap_or_else(|| String::from("detached HEAD"));
+            unexpected_branch = Some((String::from(expected), actual));
+        }
+    }
+
+    let mut annotation = String::new();
+    if options.since_tag {
+        match commits_since_tag(&repository) {
+            Some((count, tag)) => annotation.push_str(&format!(" ({} since {})", count, tag)),
+            None => annotation.push_str(" (no tags)"),
+        }
+    }
+
+    let mut unexpected_identity = None;
+    if let Some(rule) = &expectations.identity {
+        let identity = read_identity(&repository).map_err(|e| AppError::git(directory, e))?;
+        if !rule.matches(identity.name.as_deref(), identity.email.as_deref()) {
+            unexpected_identity = Some(identity);
+        }
+    }
+
+    let status = check_status(&repository, &options.check);
+    match &status {
+        Ok(report) => {
+            let statuses: Vec<&str> = report.statuses.iter().map(GitStatus::name).collect();
+            debug!(
+                "{}: {} (branch {}, {} ahead, {} behind, {} staged, {} modified, {} untracked)",
+                directory.display(),
+                if statuses.is_empty() { String::from("clean") } else { statuses.join(", ") },
+                report.branch.as_deref().unwrap_or("(none)"),
+                report.ahead,
+                report.behind,
+                report.staged_count,
+                report.modified_count,
+                report.untracked_count,
+            );
+        }
+        Err(error) => debug!("{}: status failed: {}", directory.display(), error.message()),
+    }
+
+    Ok(RepoResult {
+        status: status.map_err(|e| AppError::git(directory, e)),
+        annotation,
+        unexpected_branch,
+        unexpected_identity,
+        needs_gc,
+        ahead_of_base: options.base.as_deref().and_then(|base| commits_ahead_of(&repository, base)),
+        default_branch_drift: if options.default_branch { default_branch_drift(&repository, &options.remote) } else { None },
+        has_remotes: repository.remotes().is_ok_and(|remotes| !remotes.is_empty()),
+        last_fetch: last_fetch_time(&repository),
+    })
+}
+
+/// Appends the last commit to a report entry, shortening the commit message
+/// so the line fits in `width` columns.
+fn with_last_commit(entry: &str, commit: Option<&CommitInfo>, now: i64, width: Option<usize>) -> String {
+    let commit = match commit {
+        Some(commit) => commit,
+        None => return format!("{} [no commits]", entry),
+    };
+
+    let age = humanize_duration(now - commit.time);
+    let mut summary = commit.summary.clone();
+    if let Some(width) = width {
+        // "  * " + entry + " [" + id + " " + summary + " - " + author + ", " + age + " ago]"
+        let fixed = 4 + entry.chars().count() + 2 + commit.short_id.len() + 1 + 3
+            + commit.author.chars().count() + 2 + age.len() + 5;
+        let available = width.saturating_sub(fixed).max(MIN_SUMMARY_WIDTH);
+        if summary.chars().count() > available {
+            summary = summary.chars().take(available - 1).collect();
+            summary.push('…');
+        }
+    }
+
+    format!("{} [{} {} - {}, {} ago]", entry, commit.short_id, summary, commit.author, age)
+}
+
+/// The git directory shared by all worktrees of `repo`. Worktrees point to the
+/// repository they belong to through a `commondir` file.
+fn common_dir(repo: &Repository) -> PathBuf {
+    let git_dir = repo.path();
+    match std::fs::read_to_string(git_dir.join("commondir")) {
+        Ok(common_dir) => git_dir.join(common_dir.trim()),
+        Err(_) => git_dir.to_path_buf(),
+    }
+}
+
+fn objects_dir(repo: &Repository) -> PathBuf {
+    let objects = common_dir(repo).join("objects");
+
+    std::fs::canonicalize(&objects).unwrap_or(objects)
+}
+
+/// When `repo` last fetched: the modification time of `FETCH_HEAD`, or of the
+/// newest remote-tracking ref for repositories that never wrote one.
+fn last_fetch_time(repo: &Repository) -> Option<i64> {
+    let common_dir = common_dir(repo);
+    modification_time(&common_dir.join("FETCH_HEAD"))
+        .or_else(|| newest_modification(&common_dir.join("refs").join("remotes")))
+}
+
+fn newest_modification(directory: &Path) -> Option<i64> {
+    let entries = std::fs::read_dir(directory).ok()?;
+    entries.flatten()
+        .filter_map(|entry| match entry.file_type() {
+            Ok(file_type) if file_type.is_dir() => newest_modification(&entry.path()),
+            Ok(_) => modification_time(&entry.path()),
+            Err(_) => None,
+        })
+        .max()
+}
+
+/// Why the object directory needs `git gc`, if it does. Counting stops as soon
+/// as a threshold is exceeded, so huge repositories don't slow the scan down.
+fn gc_reason(objects: &Path, thresholds: &Maintenance) -> Option<String> {
+    let mut loose_objects = 0;
+    for fanout in std::fs::read_dir(objects).ok()?.flatten() {
+        let name = fanout.file_name();
+        let name = name.to_string_lossy();
+        if name.len() != 2 || !name.chars().all(|c| c.is_ascii_hexdigit()) {
+            continue;
+        }
+        if let Ok(entries) = std::fs::read_dir(fanout.path()) {
+            loose_objects += entries.take(thresholds.loose_objects + 1 - loose_objects).count();
+        }
+        if loose_objects > thresholds.loose_objects {
+            return Some(format!("more than {} loose objects", thresholds.loose_objects));
+        }
+    }
+
+    let mut packs = 0;
+    if let Ok(entries) = std::fs::read_dir(objects.join("pack")) {
+        for entry in entries.flatten() {
+            if entry.path().extension().is_some_and(|ext| ext == "pack") {
+                packs += 1;
+            }
+            if packs > thresholds.packs {
+                return Some(format!("more than {} packs", thresholds.packs));
+            }
+        }
+    }
+
+    None
+}
+
+fn read_identity(repo: &Repository) -> Result<Identity, Error> {
+    let config = repo.config()?;
+    let local = config.open_level(ConfigLevel::Local)?;
+
+    Ok(Identity {
+        name: config.get_string("user.name").ok(),
+        email: config.get_string("user.email").ok(),
+        local: local.get_entry("user.name").is_ok() || local.get_entry("user.email").is_ok(),
+    })
+}
+
+/// Runs `work` on a worker thread and waits at most `timeout` for it. A
+/// repository on a hung network mount can block forever, in which case the
+/// worker is abandoned and `None` is returned.
+fn run_with_timeout<T, F>(timeout: Option<Duration>, work: F) -> Option<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    let timeout = match timeout {
+        Some(timeout) => timeout,
+        None => return Some(work()),
+    };
+
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let _ = sender.send(work());
+    });
+
+    receiver.recv_timeout(timeout).ok()
+}
+
+/// How many commits HEAD has that `base` doesn't. `None` when there are none
+/// or the repository has no such ref.
+fn commits_ahead_of(repo: &Repository, base: &str) -> Option<usize> {
+    let head = repo.head().ok()?.peel_to_commit().ok()?;
+    let base = repo.revparse_single(base).ok()?.peel_to_commit().ok()?;
+    let (ahead, _) = repo.graph_ahead_behind(head.id(), base.id()).ok()?;
+
+    if ahead > 0 {
+        Some(ahead)
+    } else {
+        None
+    }
+}
+
+/// How far the local copy of `remote`'s default branch, the one
+/// `<remote>/HEAD` points to, is from it, whichever branch is checked out.
+/// `None` when they are the same, or either can't be resolved.
+fn default_branch_drift(repo: &Repository, remote: &str) -> Option<BranchStatus> {
+    let remote_head = repo.find_reference(&format!("refs/remotes/{}/HEAD", remote)).ok()?;
+    let target = remote_head.symbolic_target()?;
+    let name = target.strip_prefix(&format!("refs/remotes/{}/", remote))?;
+    let remote_oid = repo.refname_to_id(target).ok()?;
+    let local_oid = repo.refname_to_id(&format!("refs/heads/{}", name)).ok()?;
+    let (ahead, behind) = repo.graph_ahead_behind(local_oid, remote_oid).ok()?;
+
+    if ahead == 0 && behind == 0 {
+        return None;
+    }
+    Some(BranchStatus { name: String::from(name), ahead, behind })
+}
+
+/// Number of commits on HEAD since the most recent reachable tag, along with
+/// the tag name. `None` when there is no tag to describe HEAD from.
+fn commits_since_tag(repo: &Repository) -> Option<(usize, String)> {
+    let mut describe_opts = DescribeOptions::new();
+    describe_opts.describe_tags();
+    let describe = repo.describe(&describe_opts).ok()?;
+
+    // The long format is always `<tag>-<count>-g<hash>`, even right on a tag.
+    let mut format_opts = DescribeFormatOptions::new();
+    format_opts.always_use_long_format(true);
+    let description = describe.format(Some(&format_opts)).ok()?;
+
+    let mut parts = description.rsplitn(3, '-');
+    let _hash = parts.next()?;
+    let count = parts.next()?.parse().ok()?;
+    let tag = parts.next()?;
+
+    Some((count, String::from(tag)))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tempfile::TempDir;
+
+    /// The directory a scan without arguments goes through, with the config
+    /// read the way `ggs` reads it.
+    fn default_root() -> PathBuf {
+        let roots = load_config().unwrap().roots();
+        assert_eq!(roots.len(), 1);
+        resolve_root(&roots[0].path).unwrap()
+    }
+
+    // A single test, as both config files are found through the environment.
+    #[cfg(unix)]
+    #[test]
+    fn default_directory_with_trailing_newline_is_scanned() {
+        let scanned = TempDir::new().unwrap();
+        let expected = std::fs::canonicalize(scanned.path()).unwrap();
+        let with_newline = format!("{}\n", scanned.path().display());
+
+        // A hand-edited config.toml.
+        let dir = TempDir::new().unwrap();
+        let config_file = dir.path().join("config.toml");
+        std::fs::write(&config_file, format!("default_directory = {:?}\n", with_newline)).unwrap();
+        env::set_var(CONFIG_ENV, &config_file);
+        assert_eq!(default_root(), expected);
+
+        // The config.txt of older versions, read when there is no config.toml.
+        let home = TempDir::new().unwrap();
+        let config_dir = home.path().join(".config").join("ggs");
+        std::fs::create_dir_all(&config_dir).unwrap();
+        std::fs::write(config_dir.join("config.txt"), &with_newline).unwrap();
+        env::remove_var(CONFIG_ENV);
+        env::set_var("HOME", home.path());
+        assert_eq!(default_root(), expected);
+    }
 }
@@ -0,0 +1,69 @@
+use std::path::{Path, PathBuf};
+
+/// Filesystem types whose every access goes over the network. FUSE
+/// filesystems are only listed when they are known to be remote.
+const NETWORK_FILESYSTEMS: &[&str] = &[
+    "nfs", "nfs4", "cifs", "smb3", "smbfs", "ncpfs", "afs", "coda", "9p", "ceph", "glusterfs", "lustre", "davfs",
+    "fuse.sshfs", "fuse.rclone", "fuse.glusterfs", "fuse.davfs2", "fuse.s3fs", "fuse.gcsfuse",
+];
+
+/// The mounted filesystems, to tell which directories are on network ones.
+pub struct Mounts {
+    /// Mount point and filesystem type, in the order they were mounted.
+    mounts: Vec<(PathBuf, String)>,
+}
+
+impl Mounts {
+    /// Reads the mount table. Only Linux has one to read; elsewhere no
+    /// directory is considered to be on a network filesystem.
+    pub fn read() -> Mounts {
+        let table = std::fs::read_to_string("/proc/self/mounts").unwrap_or_default();
+        let mounts = table.lines()
+            .filter_map(|line| {
+                let mut fields = line.split_whitespace();
+                let _device = fields.next()?;
+                let mount_point = unescape(fields.next()?);
+                let filesystem = fields.next()?;
+                Some((PathBuf::from(mount_point), String::from(filesystem)))
+            })
+            .collect();
+
+        Mounts { mounts }
+    }
+
+    /// Whether `path`, which must be canonical, is on a network filesystem.
+    pub fn is_network(&self, path: &Path) -> bool {
+        // The longest mount point containing the path, the last one mounted
+        // when several were mounted on the same point.
+        let filesystem = self.mounts.iter()
+            .filter(|(mount_point, _)| path.starts_with(mount_point))
+            .max_by_key(|(mount_point, _)| mount_point.components().count())
+            .map(|(_, filesystem)| filesystem.as_str());
+
+        filesystem.is_some_and(|filesystem| NETWORK_FILESYSTEMS.contains(&filesystem))
+    }
+}
+
+/// Mount points have spaces, tabs, newlines and backslashes written as
+/// octal escapes such as `\040`.
+fn unescape(field: &str) -> String {
+    let mut unescaped = String::new();
+    let mut rest = field;
+    while let Some(start) = rest.find('\\') {
+        unescaped.push_str(&rest[..start]);
+        let code = rest.get(start + 1..start + 4).and_then(|digits| u8::from_str_radix(digits, 8).ok());
+        match code {
+            Some(code) => {
+                unescaped.push(char::from(code));
+                rest = &rest[start + 4..];
+            }
+            None => {
+                unescaped.push('\\');
+                rest = &rest[start + 1..];
+            }
+        }
+    }
+    unescaped.push_str(rest);
+
+    unescaped
+}
@@ -0,0 +1,77 @@
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use notify_rust::Notification;
+
+use crate::GitStatus;
+
+/// Minimum time between two notifications. Changes in between are batched.
+const DEBOUNCE: Duration = Duration::from_secs(60);
+
+/// Turns state changes between two scans into desktop notifications.
+pub struct Notifier {
+    /// Repositories that got worse since the last notification, with why.
+    pending: BTreeMap<String, &'static str>,
+    last_sent: Option<Instant>,
+    warned: bool,
+}
+
+impl Notifier {
+    pub fn new() -> Notifier {
+        Notifier {
+            pending: BTreeMap::new(),
+            last_sent: None,
+            warned: false,
+        }
+    }
+
+    /// Records repositories that became dirty, or gained unpushed commits,
+    /// between the `previous` and `current` scan.
+    pub fn record(&mut self, previous: &BTreeMap<String, Vec<GitStatus>>, current: &BTreeMap<String, Vec<GitStatus>>) {
+        // Nothing to tell about repositories that were cleaned up in the meantime.
+        self.pending.retain(|path, _| current.contains_key(path));
+
+        for (path, statuses) in current {
+            let before = previous.get(path).map(Vec::as_slice).unwrap_or(&[]);
+            let became_dirty = before.is_empty();
+            let new_unpushed = statuses.contains(&GitStatus::UnpushedCommits) && !before.contains(&GitStatus::UnpushedCommits);
+            if became_dirty || new_unpushed {
+                self.pending.insert(path.clone(), describe(statuses));
+            }
+        }
+    }
+
+    /// Sends the pending changes, unless a notification went out too recently.
+    pub fn send_if_due(&mut self) {
+        if self.pending.is_empty() || self.last_sent.is_some_and(|sent| sent.elapsed() < DEBOUNCE) {
+            return;
+        }
+
+        let body: Vec<String> = self.pending.iter().map(|(path, why)| format!("{} {}", path, why)).collect();
+        let result = Notification::new()
+            .summary("ggs")
+            .body(&body.join("\n"))
+            .show();
+        if let Err(error) = result {
+            if !self.warned {
+                eprintln!("Warning: {}. Could not send desktop notification.", error);
+                self.warned = true;
+            }
+        }
+
+        self.pending.clear();
+        self.last_sent = Some(Instant::now());
+    }
+}
+
+fn describe(statuses: &[GitStatus]) -> &'static str {
+    if statuses.iter().any(|status| matches!(status, GitStatus::InProgress(_))) {
+        "has an operation in progress"
+    } else if statuses.contains(&GitStatus::UnpushedCommits) {
+        "has unpushed commits"
+    } else if statuses.contains(&GitStatus::Staged) || statuses.contains(&GitStatus::Modified) {
+        "has uncommitted changes"
+    } else {
+        "needs attention"
+    }
+}
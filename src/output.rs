@@ -0,0 +1,405 @@
+use std::env;
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+
+use git_global_status::{GitStatus, RepoReport};
+use terminal_size::{terminal_size, Height, Width};
+
+const DEFAULT_PAGER: &str = "less -R";
+const DEFAULT_BULLET: &str = "  * ";
+
+/// Whether the report is shown through `$PAGER`.
+#[derive(Clone, Copy, PartialEq)]
+pub enum PagerMode {
+    /// Only when stdout is a terminal and the report doesn't fit on screen.
+    Auto,
+    Always,
+    Never,
+}
+
+/// How the sections of the report are laid out.
+#[derive(Clone)]
+pub struct Layout {
+    /// Print each section's heading, rather than only its entries.
+    pub headings: bool,
+    /// Put in front of every entry.
+    pub bullet: String,
+}
+
+impl Default for Layout {
+    fn default() -> Layout {
+        Layout {
+            headings: true,
+            bullet: String::from(DEFAULT_BULLET),
+        }
+    }
+}
+
+/// Appends a heading followed by one bullet per directory. Empty sections are left out.
+pub fn print_status(out: &mut String, directories: &[String], message: &str, layout: &Layout) {
+    if !directories.is_empty() {
+        if layout.headings {
+            out.push_str(&format!("{}\n", message));
+        }
+        for directory in directories {
+            out.push_str(&format!("{}{}\n", layout.bullet, directory));
+        }
+    }
+}
+
+/// Appends every group, such as a repository or an organization, followed by
+/// a bullet per entry. Without headings, each line starts with the group instead.
+pub fn print_groups<'a>(out: &mut String, groups: impl IntoIterator<Item = (&'a String, &'a Vec<String>)>, layout: &Layout) {
+    for (group, entries) in groups {
+        if layout.headings {
+            out.push_str(&format!("{}\n", group));
+        }
+        for entry in entries {
+            if layout.headings {
+                out.push_str(&format!("{}{}\n", layout.bullet, entry));
+            } else {
+                out.push_str(&format!("{}{}: {}\n", layout.bullet, group, entry));
+            }
+        }
+    }
+}
+
+/// The sections of the Markdown report, in the order they are shown.
+const MARKDOWN_SECTIONS: [(&str, &str); 9] = [
+    ("in_progress", "In progress"),
+    ("modified", "Modified"),
+    ("staged", "Staged"),
+    ("unpushed_commits", "Unpushed commits"),
+    ("upstream_gone", "Upstream gone"),
+    ("no_upstream", "No upstream"),
+    ("stale", "Stale"),
+    ("empty", "Empty"),
+    ("bare", "Bare"),
+];
+
+/// Renders `reports` as a GitHub-flavored Markdown document to paste into a
+/// wiki or issue: a heading with the scanned `roots` and when the scan
+/// started, the `summary` line, then a table per status with the repositories
+/// in it. Statuses no repository has are left out, and so are clean
+/// repositories. With a single root, repositories are shown relative to it.
+pub fn render_markdown(reports: &[&RepoReport], roots: &[PathBuf], timestamp: i64, summary: &str) -> String {
+    let mut out = String::from("# Repository status\n\n");
+    let names: Vec<String> = roots.iter().map(|root| inline_code(&root.display().to_string())).collect();
+    if names.is_empty() {
+        out.push_str(&format!("Scanned on {}.\n\n", format_utc(timestamp)));
+    } else {
+        out.push_str(&format!("Scanned {} on {}.\n\n", names.join(", "), format_utc(timestamp)));
+    }
+    out.push_str(&format!("{}.\n", summary));
+
+    let root = match roots {
+        [root] => Some(root.as_path()),
+        _ => None,
+    };
+    for (name, title) in MARKDOWN_SECTIONS {
+        let section: Vec<&RepoReport> = reports.iter()
+            .copied()
+            .filter(|report| report.statuses.iter().any(|status| status.name() == name))
+            .collect();
+        if section.is_empty() {
+            continue;
+        }
+
+        out.push_str(&format!("\n## {}\n\n", title));
+        out.push_str("| Repository | Branch | Ahead | Behind | Staged | Modified | Untracked |\n");
+        out.push_str("| --- | --- | ---: | ---: | ---: | ---: | ---: |\n");
+        for report in section {
+            let path = match root.map(|root| report.path.strip_prefix(root)) {
+                Some(Ok(relative)) if !relative.as_os_str().is_empty() => relative,
+                _ => report.path.as_path(),
+            };
+            let branch = match &report.branch {
+                Some(branch) => inline_code(branch),
+                None => String::from("(none)"),
+            };
+            out.push_str(&format!(
+                "| {} | {} | {} | {} | {} | {} | {} |\n",
+                escape_cell(&inline_code(&path.display().to_string())),
+                escape_cell(&branch),
+                report.ahead,
+                report.behind,
+                report.staged_count,
+                report.modified_count,
+                report.untracked_count,
+            ));
+        }
+    }
+
+    out
+}
+
+/// `text` as inline code, so that underscores and asterisks in it aren't
+/// taken for emphasis. Backticks in it need a longer fence.
+fn inline_code(text: &str) -> String {
+    if text.contains('`') {
+        format!("`` {} ``", text)
+    } else {
+        format!("`{}`", text)
+    }
+}
+
+/// A `|` would end the table cell, even inside inline code.
+fn escape_cell(text: &str) -> String {
+    text.replace('|', "\\|")
+}
+
+/// `seconds` since the epoch as a UTC date and time, e.g. `2024-03-01 14:05 UTC`.
+fn format_utc(seconds: i64) -> String {
+    let days = seconds.div_euclid(86_400);
+    let time = seconds.rem_euclid(86_400);
+
+    // Howard Hinnant's days_from_civil, in reverse.
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{:04}-{:02}-{:02} {:02}:{:02} UTC", year, month, day, time / 3600, time % 3600 / 60)
+}
+
+/// The columns of `--format csv`, in order. Scripts rely on it, so columns
+/// are only ever added at the end.
+const CSV_HEADER: &str = "path,status,branch,ahead,behind,last_commit";
+
+/// Renders `reports` as CSV, with a header row. `status` lists every status
+/// separated by spaces, `ahead` and `behind` are empty without an upstream,
+/// and `last_commit` is in seconds since the epoch.
+pub fn render_csv<'a>(reports: impl IntoIterator<Item = &'a RepoReport>) -> String {
+    let mut out = format!("{}\n", CSV_HEADER);
+    for report in reports {
+        let statuses: Vec<&str> = report.statuses.iter().map(GitStatus::name).collect();
+        let has_upstream = report.upstream.is_some();
+        let fields = [
+            report.path.display().to_string(),
+            if statuses.is_empty() { String::from("clean") } else { statuses.join(" ") },
+            report.branch.clone().unwrap_or_default(),
+            if has_upstream { report.ahead.to_string() } else { String::new() },
+            if has_upstream { report.behind.to_string() } else { String::new() },
+            report.last_commit.as_ref().map(|commit| commit.time.to_string()).unwrap_or_default(),
+        ];
+        let row: Vec<String> = fields.iter().map(|field| escape_csv(field)).collect();
+        out.push_str(&row.join(","));
+        out.push('\n');
+    }
+
+    out
+}
+
+/// Quotes `field` when it contains a comma, quote or line break, doubling its quotes.
+fn escape_csv(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        String::from(field)
+    }
+}
+
+/// The placeholders a `--template` may use.
+const PLACEHOLDERS: [&str; 7] = ["path", "status", "branch", "upstream", "ahead", "behind", "host"];
+
+/// A `--template` line such as `{path}: {status} on {branch}`, printed per
+/// repository. `{{` and `}}` stand for literal braces.
+#[derive(Clone)]
+pub struct Template {
+    pieces: Vec<Piece>,
+}
+
+#[derive(Clone)]
+enum Piece {
+    Text(String),
+    Placeholder(&'static str),
+}
+
+impl Template {
+    /// Fails on an unknown placeholder or an unmatched brace.
+    pub fn parse(template: &str) -> Result<Template, String> {
+        let mut pieces = Vec::new();
+        let mut text = String::new();
+        let mut chars = template.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => return Err(String::from("unclosed '{' in template, use '{{' for a literal one")),
+                        }
+                    }
+                    let placeholder = PLACEHOLDERS.iter().find(|&&placeholder| placeholder == name).ok_or_else(|| {
+                        format!("unknown placeholder '{{{}}}' in template, expected one of {{{}}}", name, PLACEHOLDERS.join("}, {"))
+                    })?;
+                    if !text.is_empty() {
+                        pieces.push(Piece::Text(std::mem::take(&mut text)));
+                    }
+                    pieces.push(Piece::Placeholder(placeholder));
+                }
+                '}' => return Err(String::from("unmatched '}' in template, use '}}' for a literal one")),
+                c => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            pieces.push(Piece::Text(text));
+        }
+
+        Ok(Template { pieces })
+    }
+
+    /// Fills in the template for `report`, without a trailing newline.
+    pub fn render(&self, report: &RepoReport) -> String {
+        let mut out = String::new();
+        for piece in &self.pieces {
+            match piece {
+                Piece::Text(text) => out.push_str(text),
+                Piece::Placeholder(name) => out.push_str(&match *name {
+                    "path" => report.path.display().to_string(),
+                    "status" => report.status.as_ref().map_or("clean", GitStatus::name).to_string(),
+                    "branch" => report.branch.clone().unwrap_or_default(),
+                    "upstream" => report.upstream.clone().unwrap_or_default(),
+                    // Left empty without an upstream to compare with, as in `render_csv`.
+                    "ahead" if report.upstream.is_none() => String::new(),
+                    "behind" if report.upstream.is_none() => String::new(),
+                    "ahead" => report.ahead.to_string(),
+                    "behind" => report.behind.to_string(),
+                    "host" => report.host.clone().unwrap_or_default(),
+                    _ => unreachable!("placeholders are checked when parsing"),
+                }),
+            }
+        }
+
+        out
+    }
+}
+
+/// A rough, human readable length of time such as "14 months" or "3 days".
+/// Each unit is used from two of it onwards, so 18 months isn't "1 year".
+pub fn humanize_duration(seconds: i64) -> String {
+    const UNITS: [(&str, i64); 5] = [
+        ("year", 365 * 24 * 60 * 60),
+        ("month", 30 * 24 * 60 * 60),
+        ("day", 24 * 60 * 60),
+        ("hour", 60 * 60),
+        ("minute", 60),
+    ];
+
+    let seconds = seconds.max(0);
+    let (unit, length) = UNITS.iter()
+        .find(|(_, length)| seconds >= 2 * length)
+        .copied()
+        .unwrap_or(("second", 1));
+
+    let value = seconds / length;
+    let plural = if value == 1 { "" } else { "s" };
+    format!("{} {}{}", value, unit, plural)
+}
+
+/// Writes the rendered report to stdout, or to the pager when one should be used.
+pub fn write_report(report: &str, mode: PagerMode) {
+    let mut pager: Option<Child> = None;
+    let mut out: Box<dyn Write> = Box::new(io::stdout());
+
+    if use_pager(report, mode) {
+        if let Some(mut child) = spawn_pager() {
+            if let Some(stdin) = child.stdin.take() {
+                out = Box::new(stdin);
+            }
+            pager = Some(child);
+        }
+    }
+
+    // Quitting the pager before reading everything closes the pipe; that's fine.
+    let _ = out.write_all(report.as_bytes());
+    let _ = out.flush();
+    drop(out);
+
+    if let Some(mut child) = pager {
+        let _ = child.wait();
+    }
+}
+
+/// Replaces `path` with `contents` by writing a temporary file next to it and
+/// renaming it over the original, so readers never see a half-written file.
+/// On Windows `rename` replaces the original too (`MOVEFILE_REPLACE_EXISTING`).
+pub fn write_file_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut tmp_name = match path.file_name() {
+        Some(name) => name.to_os_string(),
+        None => OsString::from("ggs"),
+    };
+    tmp_name.push(".tmp");
+    let tmp_path = path.with_file_name(tmp_name);
+
+    let result = File::create(&tmp_path).and_then(|mut file| {
+        file.write_all(contents)?;
+        file.sync_all()
+    });
+    if let Err(error) = result.and_then(|()| std::fs::rename(&tmp_path, path)) {
+        let _ = std::fs::remove_file(&tmp_path);
+        return Err(error);
+    }
+
+    Ok(())
+}
+
+/// Width of the terminal, `None` when stdout isn't one.
+pub fn terminal_width() -> Option<usize> {
+    if !io::stdout().is_terminal() {
+        return None;
+    }
+
+    terminal_size().map(|(Width(width), _)| width as usize)
+}
+
+fn use_pager(report: &str, mode: PagerMode) -> bool {
+    match mode {
+        PagerMode::Always => true,
+        PagerMode::Never => false,
+        PagerMode::Auto => {
+            if !io::stdout().is_terminal() {
+                return false;
+            }
+            match terminal_size() {
+                Some((_, Height(height))) => report.lines().count() >= height as usize,
+                None => false,
+            }
+        }
+    }
+}
+
+/// Starts `$PAGER`, or `less -R` when it isn't set. `None` if it can't be started.
+fn spawn_pager() -> Option<Child> {
+    let pager = match env::var("PAGER") {
+        Ok(pager) if !pager.trim().is_empty() => pager,
+        _ => String::from(DEFAULT_PAGER),
+    };
+
+    let mut parts = pager.split_whitespace();
+    let program = parts.next()?;
+
+    Command::new(program)
+        .args(parts)
+        .stdin(Stdio::piped())
+        .spawn()
+        .ok()
+}